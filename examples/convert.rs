@@ -0,0 +1,73 @@
+//! Rewrites every sequence of a store in another format.
+//!
+//! ```text
+//! cargo run --example convert -- <store dir> <binary|text> [u32|u64|u128|i64]
+//! ```
+//!
+//! The value type defaults to `u64` and has to match the type the sequences
+//! were created with.
+
+use std::env;
+use std::path::Path;
+use std::process;
+
+use file_seq::{Codec, FileSeq, FileSeqBuilder, FileSeqError, OpenMode, SeqStore, SeqValue};
+
+fn convert<T: SeqValue>(dir: &Path, codec: Codec) -> file_seq::Result<()> {
+    match FileSeq::<T>::open_typed(dir) {
+        Ok(mut seq) => {
+            seq.convert(codec)?;
+            println!("converted unnamed sequence");
+        }
+        Err(FileSeqError::NotFound) => {}
+        Err(e) => return Err(e),
+    }
+
+    let store = SeqStore::open(dir)?;
+    for name in store.list()? {
+        // Never creates a sequence, even if it was deleted since it was listed
+        let mut seq = FileSeqBuilder::<T>::new_typed(dir)
+            .name(&name)
+            .open_mode(OpenMode::OpenExisting)
+            .build()?;
+        seq.convert(codec)?;
+        println!("converted {}", name);
+    }
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (dir, codec, value_type) = match args.as_slice() {
+        [dir, codec] => (dir, codec, "u64"),
+        [dir, codec, value_type] => (dir, codec, value_type.as_str()),
+        _ => usage(),
+    };
+    let codec = match codec.as_str() {
+        "binary" => Codec::Binary,
+        "text" => Codec::Text,
+        _ => usage(),
+    };
+
+    let dir = Path::new(dir);
+    if !dir.is_dir() {
+        eprintln!("error: {} is not a directory", dir.display());
+        process::exit(1);
+    }
+    let result = match value_type {
+        "u32" => convert::<u32>(dir, codec),
+        "u64" => convert::<u64>(dir, codec),
+        "u128" => convert::<u128>(dir, codec),
+        "i64" => convert::<i64>(dir, codec),
+        _ => usage(),
+    };
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("usage: convert <store dir> <binary|text> [u32|u64|u128|i64]");
+    process::exit(2);
+}
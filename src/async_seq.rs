@@ -0,0 +1,367 @@
+use std::path::Path;
+
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+use crate::error::Result;
+use crate::files::{self, FileState, SeqFiles};
+use crate::lock::{self, AsyncSeqGuard};
+use crate::metadata::{Metadata, Requested, DEFAULT_VERSIONS};
+use crate::storage::Layout;
+use crate::{Codec, Durability, LockMode, OverflowPolicy, SeqValue};
+
+/// Async version of [`FileSeq`](crate::FileSeq), built on `tokio::fs`.
+///
+/// Uses the same files, recovery and locking as `FileSeq`, so both can be
+/// used on the same store. Concurrent tasks are serialized by an async mutex,
+/// other processes by the lock file, which is polled without blocking the
+/// executor.
+///
+/// Requires the `tokio` feature.
+///
+/// # Example
+///
+/// ```
+/// use file_seq::AsyncFileSeq;
+/// use std::path::Path;
+///
+/// # tokio::runtime::Runtime::new().unwrap().block_on(async {
+/// let dir = Path::new("/tmp/example_async");
+/// # let _ = std::fs::remove_dir_all(&dir);
+/// let initial_value = 1;
+///
+/// let seq = AsyncFileSeq::new(&dir, initial_value).await.unwrap();
+///
+/// assert_eq!(initial_value, seq.get_and_increment(1).await.unwrap());
+/// assert_eq!(initial_value + 2, seq.increment_and_get(1).await.unwrap());
+/// assert_eq!(initial_value + 2, seq.value().await.unwrap());
+/// # });
+/// ```
+#[derive(Debug)]
+pub struct AsyncFileSeq<T = u64> {
+    files: SeqFiles,
+    metadata: Metadata<T>,
+    lock_mode: LockMode,
+    durability: Durability,
+    overflow_policy: OverflowPolicy<T>,
+    codec: Codec,
+    mutex: Mutex<()>,
+}
+
+impl AsyncFileSeq {
+    pub async fn new<P: AsRef<Path>>(store_dir: P, initial_value: u64) -> Result<Self> {
+        Self::new_typed(store_dir, initial_value).await
+    }
+}
+
+impl<T: SeqValue> AsyncFileSeq<T> {
+    /// Same as [`AsyncFileSeq::new`], for value types other than `u64`.
+    pub async fn new_typed<P: AsRef<Path>>(store_dir: P, initial_value: T) -> Result<Self> {
+        fs::create_dir_all(store_dir.as_ref()).await?;
+
+        let mut seq = Self {
+            files: SeqFiles::new(store_dir, ""),
+            metadata: Requested::default().metadata(None),
+            lock_mode: LockMode::default(),
+            durability: Durability::default(),
+            overflow_policy: OverflowPolicy::default(),
+            codec: Codec::default(),
+            mutex: Mutex::new(()),
+        };
+        seq.initialize(initial_value).await?;
+
+        Ok(seq)
+    }
+
+    /// See [`FileSeq::with_lock_mode`](crate::FileSeq::with_lock_mode).
+    pub fn with_lock_mode(mut self, lock_mode: LockMode) -> Self {
+        self.lock_mode = lock_mode;
+        self
+    }
+
+    /// See [`FileSeq::with_durability`](crate::FileSeq::with_durability).
+    pub fn with_durability(mut self, durability: Durability) -> Self {
+        self.durability = durability;
+        self
+    }
+
+    /// See [`FileSeq::with_overflow_policy`](crate::FileSeq::with_overflow_policy),
+    /// the stored policy is used by default.
+    pub fn with_overflow_policy(mut self, overflow_policy: OverflowPolicy<T>) -> Result<Self> {
+        self.overflow_policy = overflow_policy.check(self.metadata.bounds())?;
+        Ok(self)
+    }
+
+    /// See [`FileSeq::with_codec`](crate::FileSeq::with_codec).
+    pub fn with_codec(mut self, codec: Codec) -> Self {
+        self.codec = codec;
+        self
+    }
+
+    async fn lock(&self) -> Result<AsyncSeqGuard<'_>> {
+        lock::acquire_all_async(&self.mutex, &self.files.lock_path, self.lock_mode).await
+    }
+
+    /// Adopts the stored settings, see `FileSeq::initialize`, and creates
+    /// the sequence with `initial_value` if it doesn't exist.
+    async fn initialize(&mut self, initial_value: T) -> Result<()> {
+        let _guard =
+            lock::acquire_all_async(&self.mutex, &self.files.lock_path, self.lock_mode).await?;
+        let stored = Metadata::from_contents(fs::read(&self.files.meta_path).await)?;
+        let has_metadata = stored.is_some();
+        match stored {
+            Some(stored) => {
+                stored.check_layout(Layout::Files)?;
+                self.metadata = stored;
+            }
+            None => self.metadata.versions = found_versions(&self.files).await,
+        }
+        self.overflow_policy = self.metadata.overflow_policy;
+
+        let value = if self.exists().await {
+            None
+        } else {
+            Some(self.metadata.bounds().check(initial_value)?)
+        };
+        if !has_metadata {
+            self.metadata.initial_value = value;
+            self.write_metadata().await?;
+        }
+        match value {
+            Some(value) => self.write(value).await,
+            None => Ok(()),
+        }
+    }
+
+    /// See `Metadata::write`.
+    async fn write_metadata(&self) -> Result<()> {
+        let mut f = fs::File::create(&self.files.meta_tmp_path).await?;
+        f.write_all(self.metadata.encode().as_bytes()).await?;
+        self.durability.sync_file_async(&f).await?;
+        drop(f);
+
+        fs::rename(&self.files.meta_tmp_path, &self.files.meta_path).await?;
+        Ok(self
+            .durability
+            .sync_dir_async(&self.files.store_dir)
+            .await?)
+    }
+
+    async fn exists(&self) -> bool {
+        for n in 1..=self.metadata.versions {
+            if fs::metadata(self.files.version_path(n)).await.is_ok() {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Deletes this sequence
+    ///
+    /// Once deleted, the sequence must be recreated. Nothing is deleted if
+    /// the lock can't be acquired.
+    pub async fn delete(&self) -> Result<()> {
+        let _guard = self.lock().await?;
+        // The files might not exist already
+        for n in 1..=self.metadata.versions {
+            let _ = fs::remove_file(self.files.version_path(n)).await;
+        }
+        let _ = fs::remove_file(&self.files.tmp_path).await;
+        let _ = fs::remove_file(&self.files.meta_path).await;
+        let _ = fs::remove_file(&self.files.meta_tmp_path).await;
+        Ok(())
+    }
+
+    /// Returns the current value of the sequence and then increments it.
+    pub async fn get_and_increment(&self, increment: T) -> Result<T> {
+        let (value, _) = self.increment(increment).await?;
+        Ok(value)
+    }
+
+    /// Increments the sequence and return the value.
+    pub async fn increment_and_get(&self, increment: T) -> Result<T> {
+        let (_, next) = self.increment(increment).await?;
+        Ok(next)
+    }
+
+    async fn increment(&self, increment: T) -> Result<(T, T)> {
+        let _guard = self.lock().await?;
+        let value = self.read().await?;
+        let next = self
+            .overflow_policy
+            .add(value, increment, self.metadata.bounds())?;
+        self.write(next).await?;
+        Ok((value, next))
+    }
+
+    /// Returns the current value of the sequence.
+    pub async fn value(&self) -> Result<T> {
+        let _guard = self.lock().await?;
+        self.read().await
+    }
+
+    async fn read(&self) -> Result<T> {
+        let mut versions = Vec::new();
+        for n in 1..=self.metadata.versions {
+            let contents = fs::read(self.files.version_path(n)).await;
+            versions.push(FileState::from_contents(contents));
+        }
+        let recovered = files::recover(&versions)?;
+        for i in recovered.discard {
+            fs::remove_file(self.files.version_path(i as u32 + 1))
+                .await
+                .ok();
+        }
+        Ok(recovered.value)
+    }
+
+    /// See `FileSeq::write` for how the files are rotated.
+    async fn write(&self, value: T) -> Result<()> {
+        let mut f = fs::File::create(&self.files.tmp_path).await?;
+        f.write_all(&self.codec.encode(value)).await?;
+        self.durability.sync_file_async(&f).await?;
+        drop(f);
+
+        for n in 2..=self.metadata.versions {
+            let path = self.files.version_path(n);
+            if fs::metadata(&path).await.is_ok() {
+                fs::rename(&path, self.files.version_path(n - 1)).await?;
+            }
+        }
+        let latest = self.files.version_path(self.metadata.versions);
+        fs::rename(&self.files.tmp_path, latest).await?;
+        Ok(self
+            .durability
+            .sync_dir_async(&self.files.store_dir)
+            .await?)
+    }
+}
+
+/// Same as `metadata::found_versions`, without blocking the executor.
+async fn found_versions(files: &SeqFiles) -> u32 {
+    let mut versions = DEFAULT_VERSIONS;
+    let mut n = 1;
+    while n <= versions + 2 {
+        if fs::metadata(files.version_path(n)).await.is_ok() {
+            versions = versions.max(n);
+        }
+        n += 1;
+    }
+    versions
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use crate::tests::tmpdir;
+    use crate::{AsyncFileSeq, FileSeq, FileSeqError, LockMode, OverflowPolicy};
+
+    #[tokio::test]
+    async fn should_increment() {
+        let seq = AsyncFileSeq::new(tmpdir(), 1).await.unwrap();
+        assert_eq!(1, seq.get_and_increment(1).await.unwrap());
+        assert_eq!(3, seq.increment_and_get(1).await.unwrap());
+        assert_eq!(3, seq.value().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn should_share_files_with_file_seq() {
+        let dir = tmpdir();
+        let seq = AsyncFileSeq::new(&dir, 1).await.unwrap();
+        seq.increment_and_get(1).await.unwrap();
+        let sync_seq = FileSeq::new(&dir, 1).unwrap();
+        assert_eq!(2, sync_seq.value().unwrap());
+        sync_seq.increment_and_get(1).unwrap();
+        assert_eq!(3, seq.value().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn should_use_stored_bounds_and_overflow_policy() {
+        let dir = tmpdir();
+        let seq = FileSeq::builder(&dir)
+            .initial_value(1)
+            .bounds(1, 10)
+            .overflow_policy(OverflowPolicy::Wrap)
+            .build()
+            .unwrap();
+        seq.set(10).unwrap();
+        let seq = AsyncFileSeq::new(&dir, 1).await.unwrap();
+        assert_eq!(1, seq.increment_and_get(1).await.unwrap());
+        let result = seq.with_overflow_policy(OverflowPolicy::WrapTo(500));
+        assert!(matches!(result, Err(FileSeqError::InvalidConfig(_))));
+    }
+
+    #[tokio::test]
+    async fn should_fall_back_to_backup_when_latest_is_corrupted() {
+        let dir = tmpdir();
+        let seq = AsyncFileSeq::new(&dir, 1).await.unwrap();
+        seq.increment_and_get(1).await.unwrap();
+        std::fs::write(dir.join("_2.seq"), [0xff; 3]).unwrap();
+        assert_eq!(1, seq.value().await.unwrap());
+        assert!(std::fs::metadata(dir.join("_2.seq")).is_err());
+    }
+
+    #[tokio::test]
+    async fn should_delete() {
+        let seq = AsyncFileSeq::new(tmpdir(), 1).await.unwrap();
+        seq.delete().await.unwrap();
+        assert!(matches!(seq.value().await, Err(FileSeqError::NotFound)));
+    }
+
+    #[tokio::test]
+    async fn should_write_and_remove_metadata() {
+        let dir = tmpdir();
+        let seq = AsyncFileSeq::new(&dir, 1).await.unwrap();
+        assert!(dir.join(".meta").exists());
+        assert!(FileSeq::builder(&dir).bounds(10, 20).build().is_err());
+
+        seq.delete().await.unwrap();
+        assert!(!dir.join(".meta").exists());
+        let seq = FileSeq::builder(&dir).bounds(10, 20).build().unwrap();
+        assert_eq!(seq.value().unwrap(), 10);
+    }
+
+    #[tokio::test]
+    async fn should_time_out_waiting_for_other_process() {
+        let dir = tmpdir();
+        let seq = AsyncFileSeq::new(&dir, 1)
+            .await
+            .unwrap()
+            .with_lock_mode(LockMode::Timeout(Duration::from_millis(50)));
+        let held = std::fs::File::open(dir.join(".lock")).unwrap();
+        held.lock().unwrap();
+        assert!(matches!(seq.value().await, Err(FileSeqError::LockTimeout)));
+        drop(held);
+        assert_eq!(1, seq.value().await.unwrap());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn should_hand_out_unique_values_across_tasks() {
+        let seq = Arc::new(AsyncFileSeq::new(tmpdir(), 1).await.unwrap());
+
+        let tasks: Vec<_> = (0..8)
+            .map(|_| {
+                let seq = Arc::clone(&seq);
+                tokio::spawn(async move {
+                    let mut values = Vec::new();
+                    for _ in 0..25 {
+                        values.push(seq.get_and_increment(1).await.unwrap());
+                    }
+                    values
+                })
+            })
+            .collect();
+
+        let mut values = HashSet::new();
+        for task in tasks {
+            for value in task.await.unwrap() {
+                assert!(values.insert(value), "{} was handed out twice", value);
+            }
+        }
+        assert_eq!(values, (1..=200).collect());
+    }
+}
@@ -0,0 +1,326 @@
+//! Command-line tool to inspect and manipulate sequences.
+//!
+//! Requires the `cli` feature.
+
+use std::env;
+use std::error::Error;
+use std::fmt::Write;
+use std::path::PathBuf;
+use std::process;
+
+use file_seq::{FileReport, FileSeqBuilder, FileStatus, OpenMode, SeqValue, VerifyReport, Version};
+
+const USAGE: &str = "\
+usage: file-seq [OPTIONS] <DIR> <COMMAND> [ARGS]
+
+Commands:
+  get            Prints the current value
+  incr [N]       Increments the sequence by N, 1 by default, and prints the new value
+  set <VALUE>    Sets the sequence to VALUE
+  init <VALUE>   Creates the sequence with VALUE
+  delete         Deletes the sequence files
+  inspect        Shows every version and which one is read
+  repair         Removes truncated or corrupted files newer than the one read
+
+get and inspect only read the store, so they work on read-only mounts. incr
+and set remove the files repair would before they write the new value.
+
+Options:
+  --name <NAME>  Uses the sequence NAME of a store, the unnamed one by default
+  --type <TYPE>  Value type of the sequence: u32, u64 (default), u128 or i64
+  --json         Prints the output as JSON
+  -h, --help     Prints this help";
+
+struct Options {
+    dir: PathBuf,
+    name: String,
+    value_type: String,
+    json: bool,
+    command: String,
+    args: Vec<String>,
+}
+
+/// What a command prints.
+enum Output<T> {
+    Value(T),
+    Deleted,
+    Report(VerifyReport<T>),
+    Repaired {
+        removed: Vec<String>,
+        report: VerifyReport<T>,
+    },
+}
+
+fn main() {
+    let options = match parse_args(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(msg) => {
+            eprintln!("error: {}\n\n{}", msg, USAGE);
+            process::exit(2);
+        }
+    };
+
+    let result = match options.value_type.as_str() {
+        "u32" => run::<u32>(&options),
+        "u64" => run::<u64>(&options),
+        "u128" => run::<u128>(&options),
+        "i64" => run::<i64>(&options),
+        other => Err(format!("unknown value type {:?}", other).into()),
+    };
+    if let Err(msg) = result {
+        if options.json {
+            println!("{{\"error\":{}}}", json_str(&msg.to_string()));
+        } else {
+            eprintln!("error: {}", msg);
+        }
+        process::exit(1);
+    }
+}
+
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+    let mut name = String::new();
+    let mut value_type = "u64".to_string();
+    let mut json = false;
+    let mut positional = Vec::new();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--name" => name = args.next().ok_or("--name requires a value")?,
+            "--type" => value_type = args.next().ok_or("--type requires a value")?,
+            "--json" => json = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            _ if arg.starts_with('-') && arg.len() > 1 && !is_number(&arg) => {
+                return Err(format!("unknown option {}", arg));
+            }
+            _ => positional.push(arg),
+        }
+    }
+
+    let mut positional = positional.into_iter();
+    let dir = positional.next().ok_or("missing store directory")?;
+    let command = positional.next().ok_or("missing command")?;
+    Ok(Options {
+        dir: PathBuf::from(dir),
+        name,
+        value_type,
+        json,
+        command,
+        args: positional.collect(),
+    })
+}
+
+/// Negative values are arguments, not options.
+fn is_number(arg: &str) -> bool {
+    arg[1..].chars().all(|c| c.is_ascii_digit())
+}
+
+fn run<T: SeqValue>(options: &Options) -> Result<(), Box<dyn Error>> {
+    let output = execute::<T>(options)?;
+    let text = if options.json {
+        json_output(&output)
+    } else {
+        text_output(&output)
+    };
+    println!("{}", text);
+    Ok(())
+}
+
+fn execute<T: SeqValue>(options: &Options) -> Result<Output<T>, Box<dyn Error>> {
+    let builder = FileSeqBuilder::<T>::new_typed(&options.dir)
+        .name(options.name.as_str())
+        .open_mode(OpenMode::OpenExisting)
+        .repair_on_read(false);
+
+    let args: Vec<&str> = options.args.iter().map(String::as_str).collect();
+    let output = match (options.command.as_str(), args.as_slice()) {
+        ("get", []) => Output::Value(builder.build_read_only()?.value()?),
+        ("incr", n) if n.len() <= 1 => {
+            let n = match n.first() {
+                Some(n) => parse_value(n)?,
+                None => T::ONE,
+            };
+            let seq = builder.build()?;
+            Output::Value(seq.increment_and_get(n)?)
+        }
+        ("set", [value]) => {
+            let value = parse_value(value)?;
+            builder.build()?.set(value)?;
+            Output::Value(value)
+        }
+        ("init", [value]) => {
+            let value = parse_value(value)?;
+            let seq = builder
+                .open_mode(OpenMode::CreateNew)
+                .initial_value(value)
+                .build()?;
+            Output::Value(seq.value()?)
+        }
+        ("delete", []) => {
+            builder.build()?.delete()?;
+            Output::Deleted
+        }
+        ("inspect", []) => Output::Report(builder.build_read_only()?.verify()?),
+        ("repair", []) => {
+            let seq = builder.build()?;
+            let removed = removed_files(&seq.repair()?);
+            Output::Repaired {
+                removed,
+                report: seq.verify()?,
+            }
+        }
+        ("get" | "incr" | "set" | "init" | "delete" | "inspect" | "repair", _) => {
+            return Err(format!("wrong arguments for {}\n\n{}", options.command, USAGE).into());
+        }
+        (command, _) => return Err(format!("unknown command {:?}", command).into()),
+    };
+    Ok(output)
+}
+
+fn parse_value<T: SeqValue>(value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value {:?}", value))
+}
+
+fn text_output<T: SeqValue>(output: &Output<T>) -> String {
+    match output {
+        Output::Value(value) => value.to_string(),
+        Output::Deleted => "deleted".to_string(),
+        Output::Report(report) => text_report(report),
+        Output::Repaired { removed, report } => {
+            let action = if removed.is_empty() {
+                "nothing to repair".to_string()
+            } else {
+                format!("removed {}", removed.join(", "))
+            };
+            format!("{}\n{}", action, text_report(report))
+        }
+    }
+}
+
+/// Returns the files a repair removes according to the report taken before
+/// it, every existing file newer than the selected one.
+fn removed_files<T: SeqValue>(report: &VerifyReport<T>) -> Vec<String> {
+    if !report.needs_repair() {
+        return Vec::new();
+    }
+    let selected = report.selected.map(|version| version.to_string());
+    files(report)
+        .into_iter()
+        .rev()
+        .take_while(|(version, _)| Some(version) != selected.as_ref())
+        .filter(|(_, file)| file.status != FileStatus::Missing)
+        .map(|(_, file)| file.path.display().to_string())
+        .collect()
+}
+
+/// Returns the files of the report with the name of their version, oldest
+/// first.
+fn files<T: SeqValue>(report: &VerifyReport<T>) -> Vec<(String, &FileReport<T>)> {
+    let mut files: Vec<_> = report
+        .older
+        .iter()
+        .enumerate()
+        .map(|(i, file)| (Version::Older(i as u32 + 1).to_string(), file))
+        .collect();
+    files.push((Version::Backup.to_string(), &report.backup));
+    files.push((Version::Latest.to_string(), &report.latest));
+    files
+}
+
+fn text_report<T: SeqValue>(report: &VerifyReport<T>) -> String {
+    let mut text = String::new();
+    for (version, file) in files(report) {
+        let status = match &file.status {
+            FileStatus::Missing => "missing".to_string(),
+            FileStatus::Truncated => "truncated".to_string(),
+            FileStatus::Corrupted { reason } => format!("corrupted: {}", reason),
+            FileStatus::Valid(value) => format!("valid: {}", value),
+            _ => "unknown".to_string(),
+        };
+        writeln!(text, "{}  {}  {}", version, file.path.display(), status).unwrap();
+    }
+    match (report.selected, report.value()) {
+        (Some(selected), Some(value)) => write!(
+            text,
+            "selected: {} ({})\nvalue: {}",
+            selected, report.reason, value
+        ),
+        _ => write!(text, "selected: none ({})", report.reason),
+    }
+    .unwrap();
+    text
+}
+
+fn json_output<T: SeqValue>(output: &Output<T>) -> String {
+    match output {
+        Output::Value(value) => format!("{{\"value\":{}}}", value),
+        Output::Deleted => "{\"deleted\":true}".to_string(),
+        Output::Report(report) => json_report(report),
+        Output::Repaired { removed, report } => format!(
+            "{{\"repaired\":{},\"report\":{}}}",
+            !removed.is_empty(),
+            json_report(report)
+        ),
+    }
+}
+
+fn json_report<T: SeqValue>(report: &VerifyReport<T>) -> String {
+    let selected = match report.selected {
+        Some(selected) => json_str(&selected.to_string()),
+        None => "null".to_string(),
+    };
+    let value = match report.value() {
+        Some(value) => value.to_string(),
+        None => "null".to_string(),
+    };
+    let older: Vec<_> = report.older.iter().map(json_file).collect();
+    format!(
+        "{{\"older\":[{}],\"backup\":{},\"latest\":{},\"selected\":{},\"reason\":{},\"value\":{}}}",
+        older.join(","),
+        json_file(&report.backup),
+        json_file(&report.latest),
+        selected,
+        json_str(&report.reason),
+        value
+    )
+}
+
+fn json_file<T: SeqValue>(file: &FileReport<T>) -> String {
+    let path = json_str(&file.path.display().to_string());
+    match &file.status {
+        FileStatus::Missing => format!("{{\"path\":{},\"status\":\"missing\"}}", path),
+        FileStatus::Truncated => format!("{{\"path\":{},\"status\":\"truncated\"}}", path),
+        FileStatus::Corrupted { reason } => format!(
+            "{{\"path\":{},\"status\":\"corrupted\",\"reason\":{}}}",
+            path,
+            json_str(reason)
+        ),
+        FileStatus::Valid(value) => {
+            format!(
+                "{{\"path\":{},\"status\":\"valid\",\"value\":{}}}",
+                path, value
+            )
+        }
+        _ => format!("{{\"path\":{},\"status\":\"unknown\"}}", path),
+    }
+}
+
+fn json_str(s: &str) -> String {
+    let mut json = String::with_capacity(s.len() + 2);
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            c if c.is_control() => write!(json, "\\u{:04x}", c as u32).unwrap(),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
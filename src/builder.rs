@@ -0,0 +1,867 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::{FileSeqError, Result};
+use crate::high_water::HighWaterMark;
+use crate::metadata::Requested;
+use crate::overflow::Bounds;
+use crate::storage::{FsStorage, SeqStorage};
+use crate::store::validate_name;
+use crate::{Codec, Durability, FileSeq, LockMode, OverflowPolicy, ReadOnlyFileSeq, SeqValue};
+
+/// Whether opening a sequence may create it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OpenMode {
+    /// Opens the sequence, creating it with the initial value if it doesn't
+    /// exist.
+    #[default]
+    CreateOrOpen,
+    /// Fails with [`FileSeqError::NotFound`] if the sequence doesn't exist.
+    OpenExisting,
+    /// Fails with [`FileSeqError::AlreadyExists`] if the sequence exists.
+    CreateNew,
+}
+
+/// Configures how a [`FileSeq`] is opened.
+///
+/// # Example
+///
+/// ```
+/// use file_seq::{Durability, FileSeq, OpenMode, OverflowPolicy};
+/// use std::path::Path;
+///
+/// let dir = Path::new("/tmp/example_builder");
+/// # let _ = std::fs::remove_dir_all(&dir);
+///
+/// let seq = FileSeq::builder(&dir)
+///     .name("tickets")
+///     .open_mode(OpenMode::CreateNew)
+///     .initial_value(1)
+///     .bounds(1, 3)
+///     .overflow_policy(OverflowPolicy::Wrap)
+///     .durability(Durability::File)
+///     .build()
+///     .unwrap();
+///
+/// assert_eq!(1, seq.next().unwrap());
+/// assert_eq!(2, seq.next().unwrap());
+/// assert_eq!(3, seq.next().unwrap());
+/// assert_eq!(1, seq.next().unwrap());
+/// ```
+#[derive(Debug, Clone)]
+pub struct FileSeqBuilder<T = u64> {
+    store_dir: PathBuf,
+    name: String,
+    create_dir: bool,
+    open_mode: OpenMode,
+    initial_value: Option<T>,
+    lock_mode: LockMode,
+    durability: Durability,
+    overflow_policy: Option<OverflowPolicy<T>>,
+    step: Option<T>,
+    bounds: Option<Bounds<T>>,
+    versions: Option<u32>,
+    owner: Option<String>,
+    file_mode: Option<u32>,
+    floor: Option<T>,
+    high_water_mark: Option<PathBuf>,
+    codec: Codec,
+    repair_on_read: bool,
+    recovery_gap: Option<T>,
+}
+
+impl FileSeqBuilder {
+    /// Starts configuring the `u64` sequence in `store_dir`.
+    pub fn new<P: AsRef<Path>>(store_dir: P) -> Self {
+        Self::new_typed(store_dir)
+    }
+}
+
+impl<T: SeqValue> FileSeqBuilder<T> {
+    /// Same as [`FileSeqBuilder::new`], for value types other than `u64`.
+    pub fn new_typed<P: AsRef<Path>>(store_dir: P) -> Self {
+        Self {
+            store_dir: store_dir.as_ref().to_path_buf(),
+            name: String::new(),
+            create_dir: true,
+            open_mode: OpenMode::default(),
+            initial_value: None,
+            lock_mode: LockMode::default(),
+            durability: Durability::default(),
+            overflow_policy: None,
+            step: None,
+            bounds: None,
+            versions: None,
+            owner: None,
+            file_mode: None,
+            floor: None,
+            high_water_mark: None,
+            codec: Codec::default(),
+            repair_on_read: true,
+            recovery_gap: None,
+        }
+    }
+
+    /// Prefixes the file names of the sequence with `name`, so that several
+    /// sequences can share a directory. See [`SeqStore`](crate::SeqStore)
+    /// for the rules names have to follow.
+    pub fn name<S: Into<String>>(mut self, name: S) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// Sets whether the store directory is created if it doesn't exist.
+    ///
+    /// Defaults to `true`.
+    pub fn create_dir(mut self, create_dir: bool) -> Self {
+        self.create_dir = create_dir;
+        self
+    }
+
+    /// Defaults to [`OpenMode::CreateOrOpen`].
+    pub fn open_mode(mut self, open_mode: OpenMode) -> Self {
+        self.open_mode = open_mode;
+        self
+    }
+
+    /// Sets the value a newly created sequence starts with.
+    ///
+    /// Defaults to zero, or the closest bound if zero is out of bounds.
+    pub fn initial_value(mut self, initial_value: T) -> Self {
+        self.initial_value = Some(initial_value);
+        self
+    }
+
+    /// See [`FileSeq::with_lock_mode`].
+    pub fn lock_mode(mut self, lock_mode: LockMode) -> Self {
+        self.lock_mode = lock_mode;
+        self
+    }
+
+    /// See [`FileSeq::with_durability`].
+    pub fn durability(mut self, durability: Durability) -> Self {
+        self.durability = durability;
+        self
+    }
+
+    /// See [`FileSeq::with_overflow_policy`].
+    ///
+    /// The policy is stored with the sequence when it's created. Opening it
+    /// with another policy fails with [`FileSeqError::InvalidConfig`], not
+    /// setting one uses the stored policy.
+    pub fn overflow_policy(mut self, overflow_policy: OverflowPolicy<T>) -> Self {
+        self.overflow_policy = Some(overflow_policy);
+        self
+    }
+
+    /// See [`FileSeq::with_codec`].
+    pub fn codec(mut self, codec: Codec) -> Self {
+        self.codec = codec;
+        self
+    }
+
+    /// Sets whether [`FileSeq::value`] removes a corrupted latest file.
+    ///
+    /// Defaults to `true`. Disable it to keep the files as they are for
+    /// audits, [`FileSeq::repair`] and updates of the sequence still remove
+    /// the corrupted file.
+    pub fn repair_on_read(mut self, repair_on_read: bool) -> Self {
+        self.repair_on_read = repair_on_read;
+        self
+    }
+
+    /// Skips ahead by `gap` whenever the value has to be recovered from the
+    /// backup, so values handed out before a crash are never repeated.
+    ///
+    /// The latest file is only missing or corrupted after a crash or a disk
+    /// error, and the backup then holds the value before it, which may
+    /// already have been handed out. Choose a gap at least as large as the
+    /// largest increment or block reserved. Skips are logged and reported by
+    /// [`FileSeq::last_skip`].
+    ///
+    /// Disabled by default.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use file_seq::FileSeq;
+    /// use std::path::Path;
+    ///
+    /// let dir = Path::new("/tmp/example_recovery_gap");
+    /// # let _ = std::fs::remove_dir_all(&dir);
+    ///
+    /// let seq = FileSeq::builder(&dir)
+    ///     .initial_value(1)
+    ///     .recovery_gap(100)
+    ///     .build()
+    ///     .unwrap();
+    /// assert_eq!(1, seq.next().unwrap());
+    ///
+    /// std::fs::write(dir.join("_2.seq"), "garbage").unwrap();
+    ///
+    /// assert_eq!(101, seq.next().unwrap());
+    /// assert_eq!(Some(101), seq.last_skip().map(|skip| skip.to));
+    /// ```
+    pub fn recovery_gap(mut self, gap: T) -> Self {
+        self.recovery_gap = Some(gap);
+        self
+    }
+
+    /// Sets the increment of [`FileSeq::next`].
+    ///
+    /// Defaults to one. Like the bounds, the step is stored with the
+    /// sequence when it's created.
+    pub fn step(mut self, step: T) -> Self {
+        self.step = Some(step);
+        self
+    }
+
+    /// Limits the sequence to values between `min` and `max`, both inclusive.
+    ///
+    /// Moving past either end is handled by the overflow policy, setting a
+    /// value out of bounds fails with [`FileSeqError::OutOfBounds`].
+    ///
+    /// The bounds are stored with the sequence when it's created. Opening it
+    /// with other bounds fails with [`FileSeqError::InvalidConfig`], not
+    /// setting any uses the stored bounds.
+    pub fn bounds(mut self, min: T, max: T) -> Self {
+        self.bounds = Some(Bounds { min, max });
+        self
+    }
+
+    /// Keeps the last `versions` values, `_1.seq` for the oldest to
+    /// `_n.seq` for the latest, so the value can be recovered from an older
+    /// one when several recent files are lost or corrupted.
+    ///
+    /// Defaults to two, the latest value and a backup, and has to be at
+    /// least that. Like the bounds, the number of versions is stored with
+    /// the sequence when it's created.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use file_seq::FileSeq;
+    /// use std::path::Path;
+    ///
+    /// let dir = Path::new("/tmp/example_versions");
+    /// # let _ = std::fs::remove_dir_all(&dir);
+    ///
+    /// let seq = FileSeq::builder(&dir)
+    ///     .initial_value(1)
+    ///     .versions(3)
+    ///     .build()
+    ///     .unwrap();
+    /// seq.increment_and_get(1).unwrap();
+    /// seq.increment_and_get(1).unwrap();
+    ///
+    /// std::fs::write(dir.join("_3.seq"), "garbage").unwrap();
+    /// std::fs::write(dir.join("_2.seq"), "garbage").unwrap();
+    ///
+    /// assert_eq!(1, seq.value().unwrap());
+    /// ```
+    pub fn versions(mut self, versions: u32) -> Self {
+        self.versions = Some(versions);
+        self
+    }
+
+    /// Sets the owner recorded in the [`Metadata`](crate::Metadata) of a
+    /// created sequence.
+    ///
+    /// Defaults to the user running the process.
+    pub fn owner<S: Into<String>>(mut self, owner: S) -> Self {
+        self.owner = Some(owner.into());
+        self
+    }
+
+    /// Sets the permissions of the files created for the sequence, e.g.
+    /// `0o660` to share it with a group.
+    ///
+    /// Defaults to the permissions the process creates files with.
+    #[cfg(unix)]
+    pub fn file_mode(mut self, file_mode: u32) -> Self {
+        self.file_mode = Some(file_mode);
+        self
+    }
+
+    /// Sets the lowest value the sequence can be created with, e.g. the
+    /// highest ID found in a database. A larger initial value is kept.
+    ///
+    /// Only applies when the sequence is created, an existing sequence keeps
+    /// its value.
+    pub fn floor(mut self, floor: T) -> Self {
+        self.floor = Some(floor);
+        self
+    }
+
+    /// Records the highest value the sequence ever stored in the file at
+    /// `path`, and creates the sequence above it.
+    ///
+    /// If the store directory is wiped or replaced by an empty one, the
+    /// sequence is created one step above the recorded value instead of at
+    /// the initial value, so values handed out before are never repeated.
+    /// Keep the file outside the store directory, ideally on another disk.
+    ///
+    /// The mark only moves up, so it's meant for sequences that don't wrap
+    /// or get decremented. A corrupted mark makes creating the sequence fail
+    /// with [`FileSeqError::Corrupted`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use file_seq::FileSeq;
+    /// use std::path::Path;
+    ///
+    /// let dir = Path::new("/tmp/example_high_water_mark");
+    /// # let _ = std::fs::remove_dir_all(&dir);
+    /// let mark = Path::new("/tmp/example_high_water_mark.hwm");
+    /// # let _ = std::fs::remove_file(&mark);
+    ///
+    /// let seq = FileSeq::builder(&dir).high_water_mark(&mark).build().unwrap();
+    /// seq.increment_and_get(10).unwrap();
+    ///
+    /// std::fs::remove_dir_all(&dir).unwrap();
+    ///
+    /// let seq = FileSeq::builder(&dir).high_water_mark(&mark).build().unwrap();
+    /// assert_eq!(11, seq.value().unwrap());
+    /// ```
+    pub fn high_water_mark<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.high_water_mark = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Opens the sequence.
+    pub fn build(self) -> Result<FileSeq<T>> {
+        if !self.name.is_empty() {
+            validate_name(&self.name)?;
+        }
+        let requested = self.requested()?;
+
+        if self.create_dir && self.open_mode != OpenMode::OpenExisting {
+            fs::create_dir_all(&self.store_dir)?;
+        } else if fs::metadata(&self.store_dir).is_err() {
+            return Err(FileSeqError::NotFound);
+        }
+
+        let storage = FsStorage {
+            file_mode: self.file_mode,
+            ..FsStorage::new(&self.store_dir, &self.name)
+        };
+        self.open(storage, requested)
+    }
+
+    /// Opens the sequence kept in `storage`, see [`SeqStorage`].
+    ///
+    /// The store directory, the name and whether the directory is created
+    /// are ignored, the storage decides where the sequence is kept.
+    pub fn build_with_storage<S: SeqStorage>(self, storage: S) -> Result<FileSeq<T, S>> {
+        let requested = self.requested()?;
+        self.open(storage, requested)
+    }
+
+    /// Checks the settings, stored ones are checked when the sequence is
+    /// opened.
+    fn requested(&self) -> Result<Requested<T>> {
+        let requested = Requested {
+            step: self.step,
+            bounds: self.bounds,
+            overflow_policy: self.overflow_policy,
+            versions: self.versions,
+            owner: self.owner.clone(),
+        };
+        let bounds = requested.bounds();
+        if bounds.min > bounds.max {
+            return Err(invalid_config("minimum is greater than maximum"));
+        }
+        if requested.step() <= T::ZERO {
+            return Err(invalid_config("step must be greater than zero"));
+        }
+        if requested.versions() < 2 {
+            return Err(invalid_config("at least two versions must be kept"));
+        }
+        if self.recovery_gap.is_some_and(|gap| gap <= T::ZERO) {
+            return Err(invalid_config("recovery gap must be greater than zero"));
+        }
+        requested.overflow_policy().check(bounds)?;
+        if let Some(value) = self.initial_value {
+            bounds.check(value)?;
+        }
+        Ok(requested)
+    }
+
+    fn open<S: SeqStorage>(self, storage: S, requested: Requested<T>) -> Result<FileSeq<T, S>> {
+        let mut seq = FileSeq {
+            lock_mode: self.lock_mode,
+            durability: self.durability,
+            file_mode: self.file_mode,
+            high_water_mark: self.high_water_mark.map(HighWaterMark::new),
+            codec: self.codec,
+            repair_on_read: self.repair_on_read,
+            recovery_gap: self.recovery_gap,
+            ..FileSeq::unopened(storage)
+        };
+        seq.initialize(self.open_mode, self.initial_value, self.floor, requested)?;
+
+        Ok(seq)
+    }
+
+    /// Opens the existing sequence for reading only, see [`ReadOnlyFileSeq`].
+    ///
+    /// Only the store directory and the name are used, the sequence is
+    /// never created.
+    pub fn build_read_only(self) -> Result<ReadOnlyFileSeq<T>> {
+        if !self.name.is_empty() {
+            validate_name(&self.name)?;
+        }
+        ReadOnlyFileSeq::open(&self.store_dir, &self.name)
+    }
+}
+
+fn invalid_config(msg: &str) -> FileSeqError {
+    FileSeqError::InvalidConfig(msg.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use crate::files::FileState;
+    use crate::tests::tmpdir;
+    use crate::{FileSeq, FileSeqBuilder, FileSeqError, OpenMode, OverflowPolicy, Version};
+
+    #[test]
+    fn should_open_existing_sequence_only() {
+        let dir = tmpdir();
+        let result = FileSeq::builder(&dir)
+            .open_mode(OpenMode::OpenExisting)
+            .build();
+        assert!(matches!(result, Err(FileSeqError::NotFound)));
+        assert!(std::fs::read_dir(&dir).unwrap().all(|e| {
+            // Only the lock file may be created
+            e.unwrap().file_name() == ".lock"
+        }));
+
+        FileSeq::new(&dir, 5).unwrap();
+        let seq = FileSeq::builder(&dir)
+            .open_mode(OpenMode::OpenExisting)
+            .build()
+            .unwrap();
+        assert_eq!(5, seq.value().unwrap());
+    }
+
+    #[test]
+    fn should_create_new_sequence_only() {
+        let dir = tmpdir();
+        let seq = FileSeq::builder(&dir)
+            .open_mode(OpenMode::CreateNew)
+            .initial_value(5)
+            .build()
+            .unwrap();
+        assert_eq!(5, seq.value().unwrap());
+        let result = FileSeq::builder(&dir)
+            .open_mode(OpenMode::CreateNew)
+            .build();
+        assert!(matches!(result, Err(FileSeqError::AlreadyExists)));
+    }
+
+    #[test]
+    fn should_not_create_dir_if_disabled() {
+        let dir = tmpdir().join("missing");
+        let result = FileSeq::builder(&dir).create_dir(false).build();
+        assert!(matches!(result, Err(FileSeqError::NotFound)));
+        assert!(std::fs::metadata(&dir).is_err());
+    }
+
+    #[test]
+    fn should_use_name_as_prefix() {
+        let dir = tmpdir();
+        FileSeq::builder(&dir).name("orders").build().unwrap();
+        assert!(std::fs::metadata(dir.join("orders_2.seq")).is_ok());
+        let result = FileSeq::builder(&dir).name("../orders").build();
+        assert!(matches!(result, Err(FileSeqError::InvalidName(_))));
+    }
+
+    #[test]
+    fn should_default_initial_value_to_bounds() {
+        let seq = FileSeq::builder(tmpdir()).build().unwrap();
+        assert_eq!(0, seq.value().unwrap());
+        let seq = FileSeq::builder(tmpdir()).bounds(10, 20).build().unwrap();
+        assert_eq!(10, seq.value().unwrap());
+        let seq = FileSeqBuilder::<i64>::new_typed(tmpdir())
+            .bounds(-20, -10)
+            .build()
+            .unwrap();
+        assert_eq!(-10, seq.value().unwrap());
+    }
+
+    #[test]
+    fn should_enforce_bounds() {
+        let seq = FileSeq::builder(tmpdir())
+            .initial_value(1)
+            .bounds(1, 10)
+            .build()
+            .unwrap();
+        assert_eq!(10, seq.increment_and_get(9).unwrap());
+        assert!(matches!(
+            seq.increment_and_get(1),
+            Err(FileSeqError::Overflow)
+        ));
+        assert!(matches!(
+            seq.decrement_and_get(10),
+            Err(FileSeqError::Overflow)
+        ));
+        assert!(matches!(seq.set(11), Err(FileSeqError::OutOfBounds)));
+        assert!(matches!(
+            seq.compare_and_set(10, 0),
+            Err(FileSeqError::OutOfBounds)
+        ));
+        assert_eq!(10, seq.value().unwrap());
+    }
+
+    #[test]
+    fn should_reject_invalid_config() {
+        let dir = tmpdir();
+        let invalid = [
+            FileSeq::builder(&dir).bounds(10, 1).build(),
+            FileSeq::builder(&dir).step(0).build(),
+            FileSeq::builder(&dir).recovery_gap(0).build(),
+            FileSeq::builder(&dir).versions(1).build(),
+            FileSeq::builder(&dir)
+                .bounds(1, 10)
+                .overflow_policy(OverflowPolicy::WrapTo(11))
+                .build(),
+        ];
+        for result in invalid {
+            assert!(matches!(result, Err(FileSeqError::InvalidConfig(_))));
+        }
+        let result = FileSeq::builder(&dir)
+            .initial_value(11)
+            .bounds(1, 10)
+            .build();
+        assert!(matches!(result, Err(FileSeqError::OutOfBounds)));
+    }
+
+    #[test]
+    fn should_skip_ahead_after_recovering_from_backup() {
+        let dir = tmpdir();
+        let seq = FileSeq::builder(&dir)
+            .initial_value(1)
+            .recovery_gap(10)
+            .build()
+            .unwrap();
+        assert_eq!(1, seq.next().unwrap());
+        assert_eq!(2, seq.next().unwrap());
+        assert_eq!(None, seq.last_skip());
+
+        std::fs::write(dir.join("_2.seq"), [0xff; 12]).unwrap();
+        assert_eq!(12, seq.value().unwrap());
+        let skip = seq.last_skip().unwrap();
+        assert_eq!((2, 12), (skip.from, skip.to));
+        // The skip is stored, so it's only made once
+        assert!(!seq.verify().unwrap().needs_repair());
+        assert_eq!(12, seq.next().unwrap());
+
+        std::fs::remove_file(dir.join("_2.seq")).unwrap();
+        assert_eq!(22, seq.next().unwrap());
+        assert_eq!(Some(22), seq.last_skip().map(|skip| skip.to));
+    }
+
+    #[test]
+    fn should_not_store_skip_without_repair_on_read() {
+        let dir = tmpdir();
+        let seq = FileSeq::builder(&dir)
+            .initial_value(1)
+            .recovery_gap(10)
+            .repair_on_read(false)
+            .build()
+            .unwrap();
+        seq.next().unwrap();
+        std::fs::write(dir.join("_2.seq"), [0xff; 12]).unwrap();
+        assert_eq!(11, seq.value().unwrap());
+        assert_eq!(11, seq.value().unwrap());
+        assert!(seq.verify().unwrap().needs_repair());
+        assert_eq!(11, seq.next().unwrap());
+    }
+
+    #[test]
+    fn should_step() {
+        let seq = FileSeq::builder(tmpdir()).step(5).build().unwrap();
+        assert_eq!(0, seq.next().unwrap());
+        assert_eq!(5, seq.next().unwrap());
+        assert_eq!(10, seq.value().unwrap());
+    }
+
+    #[test]
+    fn should_create_sequence_at_floor() {
+        let seq = FileSeq::builder(tmpdir())
+            .initial_value(1)
+            .floor(100)
+            .build()
+            .unwrap();
+        assert_eq!(100, seq.value().unwrap());
+        let seq = FileSeq::builder(tmpdir())
+            .initial_value(200)
+            .floor(100)
+            .build()
+            .unwrap();
+        assert_eq!(200, seq.value().unwrap());
+    }
+
+    #[test]
+    fn should_keep_value_of_existing_sequence_below_floor() {
+        let dir = tmpdir();
+        FileSeq::new(&dir, 1).unwrap();
+        let seq = FileSeq::builder(&dir).floor(100).build().unwrap();
+        assert_eq!(1, seq.value().unwrap());
+    }
+
+    #[test]
+    fn should_recreate_sequence_above_high_water_mark() {
+        let dir = tmpdir();
+        let mark = tmpdir().join("seq.hwm");
+        let builder = FileSeq::builder(dir.join("store"))
+            .initial_value(1)
+            .step(5)
+            .high_water_mark(&mark);
+        let seq = builder.clone().build().unwrap();
+        assert_eq!(1, seq.next().unwrap());
+        assert_eq!(6, seq.next().unwrap());
+        seq.set(3).unwrap();
+        seq.delete().unwrap();
+
+        let seq = builder.build().unwrap();
+        assert_eq!(16, seq.next().unwrap());
+    }
+
+    #[test]
+    fn should_fail_on_corrupted_high_water_mark() {
+        let mark = tmpdir().join("seq.hwm");
+        std::fs::write(&mark, [0xff; 3]).unwrap();
+        let result = FileSeq::builder(tmpdir()).high_water_mark(&mark).build();
+        assert!(matches!(result, Err(FileSeqError::Corrupted)));
+    }
+
+    #[test]
+    fn should_keep_corrupted_latest_on_read_if_disabled() {
+        let dir = tmpdir();
+        let seq = FileSeq::builder(&dir)
+            .initial_value(1)
+            .repair_on_read(false)
+            .build()
+            .unwrap();
+        seq.increment_and_get(1).unwrap();
+        std::fs::write(dir.join("_2.seq"), [0xff; 12]).unwrap();
+        assert_eq!(1, seq.value().unwrap());
+        assert!(std::fs::metadata(dir.join("_2.seq")).is_ok());
+
+        // Updates must not rotate the corrupted file over the backup
+        assert_eq!(2, seq.increment_and_get(1).unwrap());
+        assert_eq!(FileState::Valid(1u64), FileState::read(dir.join("_1.seq")));
+    }
+
+    #[test]
+    fn should_store_bounds_and_overflow_policy() {
+        let dir = tmpdir();
+        FileSeq::builder(&dir)
+            .initial_value(1)
+            .bounds(1, 999)
+            .overflow_policy(OverflowPolicy::Wrap)
+            .build()
+            .unwrap();
+
+        let seq = FileSeq::new(&dir, 1).unwrap();
+        seq.set(999).unwrap();
+        assert_eq!(1, seq.increment_and_get(1).unwrap());
+        assert!(matches!(seq.set(1000), Err(FileSeqError::OutOfBounds)));
+        seq.set(995).unwrap();
+        assert_eq!(1..11, seq.reserve(10).unwrap());
+
+        let seq = FileSeq::builder(&dir)
+            .bounds(1, 999)
+            .overflow_policy(OverflowPolicy::Wrap)
+            .build()
+            .unwrap();
+        assert_eq!(11, seq.value().unwrap());
+    }
+
+    #[test]
+    fn should_record_metadata() {
+        let dir = tmpdir();
+        let before = SystemTime::now() - Duration::from_secs(1);
+        let seq = FileSeq::builder(&dir)
+            .initial_value(1)
+            .floor(10)
+            .step(5)
+            .owner("billing")
+            .build()
+            .unwrap();
+        let metadata = seq.metadata().clone();
+        assert_eq!(Some(10), metadata.initial_value);
+        assert_eq!(5, metadata.step);
+        assert_eq!((0, u64::MAX), (metadata.min, metadata.max));
+        assert_eq!("billing", metadata.owner);
+        assert!(metadata.created >= before && metadata.created <= SystemTime::now());
+
+        let seq = FileSeq::builder(&dir)
+            .owner("someone else")
+            .build()
+            .unwrap();
+        assert_eq!(&metadata, seq.metadata());
+        assert_eq!(15, seq.increment_and_get(5).unwrap());
+        assert_eq!(15, seq.next().unwrap());
+        assert_eq!(20, seq.value().unwrap());
+    }
+
+    #[test]
+    fn should_not_record_initial_value_of_existing_sequence() {
+        let dir = tmpdir();
+        FileSeq::new(&dir, 5).unwrap();
+        std::fs::remove_file(dir.join(".meta")).unwrap();
+        let seq = FileSeq::new(&dir, 7).unwrap();
+        assert_eq!(None, seq.metadata().initial_value);
+        assert_eq!(None, FileSeq::open(&dir).unwrap().metadata().initial_value);
+    }
+
+    #[test]
+    fn should_reject_conflicting_settings() {
+        let dir = tmpdir();
+        FileSeq::builder(&dir)
+            .initial_value(1)
+            .bounds(1, 999)
+            .build()
+            .unwrap();
+        for result in [
+            FileSeq::builder(&dir).bounds(1, 1000).build(),
+            FileSeq::builder(&dir).step(2).build(),
+            FileSeq::builder(&dir).versions(3).build(),
+            FileSeq::builder(&dir)
+                .overflow_policy(OverflowPolicy::Wrap)
+                .build(),
+        ] {
+            assert!(matches!(result, Err(FileSeqError::InvalidConfig(_))));
+        }
+    }
+
+    #[test]
+    fn should_store_settings_of_existing_sequence() {
+        let dir = tmpdir();
+        FileSeq::new(&dir, 5).unwrap();
+        std::fs::remove_file(dir.join(".meta")).unwrap();
+
+        FileSeq::builder(&dir).bounds(0, 10).build().unwrap();
+        let result = FileSeq::builder(&dir).bounds(0, 20).build();
+        assert!(matches!(result, Err(FileSeqError::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn should_forget_settings_of_deleted_sequence() {
+        let dir = tmpdir();
+        let seq = FileSeq::builder(&dir).bounds(0, 10).build().unwrap();
+        seq.delete().unwrap();
+        assert!(std::fs::metadata(dir.join(".meta")).is_err());
+        let seq = FileSeq::builder(&dir).bounds(0, 20).build().unwrap();
+        assert!(seq.set(20).is_ok());
+    }
+
+    #[test]
+    fn should_keep_versions() {
+        let dir = tmpdir();
+        let seq = FileSeq::builder(&dir)
+            .initial_value(1)
+            .versions(5)
+            .build()
+            .unwrap();
+        for _ in 0..6 {
+            seq.increment_and_get(1).unwrap();
+        }
+        for n in 1..=5u64 {
+            let path = dir.join(format!("_{}.seq", n));
+            assert_eq!(FileState::Valid(n + 2), FileState::read(path));
+        }
+        assert!(std::fs::metadata(dir.join("_6.seq")).is_err());
+        assert_eq!(5, FileSeq::open(&dir).unwrap().metadata().versions);
+
+        for n in [5, 4, 3] {
+            std::fs::write(dir.join(format!("_{}.seq", n)), "garbage").unwrap();
+        }
+        let report = seq.verify().unwrap();
+        assert_eq!(Some(Version::Older(2)), report.selected);
+        assert_eq!(3, report.older.len());
+        assert!(report.needs_repair());
+        assert_eq!(4, seq.value().unwrap());
+        assert_eq!(5, seq.increment_and_get(1).unwrap());
+        assert_eq!(5, seq.value().unwrap());
+    }
+
+    #[test]
+    fn should_read_legacy_sequence_with_more_versions() {
+        let dir = tmpdir();
+        let seq = FileSeq::new(&dir, 5).unwrap();
+        seq.increment_and_get(1).unwrap();
+        std::fs::remove_file(dir.join(".meta")).unwrap();
+
+        let seq = FileSeq::builder(&dir).versions(3).build().unwrap();
+        assert_eq!(3, seq.metadata().versions);
+        assert_eq!(6, seq.value().unwrap());
+        assert_eq!(7, seq.increment_and_get(1).unwrap());
+        assert_eq!(FileState::Valid(7u64), FileState::read(dir.join("_3.seq")));
+        assert_eq!(FileState::Valid(6u64), FileState::read(dir.join("_1.seq")));
+    }
+
+    #[test]
+    fn should_find_versions_of_sequence_that_lost_metadata() {
+        let dir = tmpdir();
+        let seq = FileSeq::builder(&dir)
+            .initial_value(1)
+            .versions(5)
+            .build()
+            .unwrap();
+        seq.increment_and_get(10).unwrap();
+        std::fs::remove_file(dir.join(".meta")).unwrap();
+
+        let result = FileSeq::builder(&dir).versions(3).build();
+        assert!(matches!(result, Err(FileSeqError::InvalidConfig(_))));
+        let seq = FileSeq::new(&dir, 1).unwrap();
+        assert_eq!(5, seq.metadata().versions);
+        assert_eq!(11, seq.value().unwrap());
+        assert_eq!(12, seq.increment_and_get(1).unwrap());
+
+        std::fs::remove_file(dir.join(".meta")).unwrap();
+        let observer = FileSeq::open_read_only(&dir).unwrap();
+        assert_eq!(12, observer.value().unwrap());
+        FileSeq::<u64>::unopened_in(&dir, "")
+            .unwrap()
+            .delete()
+            .unwrap();
+        assert!(std::fs::read_dir(&dir).unwrap().all(|entry| !entry
+            .unwrap()
+            .path()
+            .to_string_lossy()
+            .ends_with(".seq")));
+    }
+
+    #[test]
+    fn should_reject_corrupted_metadata() {
+        let dir = tmpdir();
+        FileSeq::new(&dir, 5).unwrap();
+        std::fs::write(dir.join(".meta"), "garbage").unwrap();
+        let result = FileSeq::new(&dir, 5);
+        assert!(matches!(result, Err(FileSeqError::Corrupted)));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn should_create_files_with_mode() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tmpdir();
+        let seq = FileSeq::builder(&dir).file_mode(0o600).build().unwrap();
+        seq.increment_and_get(1).unwrap();
+        for file in ["_1.seq", "_2.seq", ".lock", ".meta"] {
+            let mode = std::fs::metadata(dir.join(file))
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(0o600, mode & 0o777, "{}", file);
+        }
+    }
+}
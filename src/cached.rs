@@ -0,0 +1,157 @@
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use crate::error::{FileSeqError, Result};
+use crate::FileSeq;
+
+/// Hands out values of a [`FileSeq`] from blocks reserved with
+/// [`FileSeq::reserve`].
+///
+/// Values are served from memory and the store is touched once per block.
+/// Once half of the current block is used, the next one is reserved on a
+/// background thread, so callers rarely wait for the disk.
+///
+/// Values of reserved blocks that weren't handed out are lost when the
+/// `CachedFileSeq` is dropped or the process crashes. The sequence then has
+/// gaps, but never repeats a value.
+///
+/// # Example
+///
+/// ```
+/// use file_seq::{CachedFileSeq, FileSeq};
+/// use std::path::Path;
+///
+/// let dir = Path::new("/tmp/example_cached");
+/// # let _ = std::fs::remove_dir_all(&dir);
+///
+/// let seq = CachedFileSeq::new(FileSeq::new(&dir, 1).unwrap(), 1000);
+///
+/// assert_eq!(1, seq.next().unwrap());
+/// assert_eq!(2, seq.next().unwrap());
+/// ```
+#[derive(Debug)]
+pub struct CachedFileSeq {
+    seq: Arc<FileSeq>,
+    block_size: u64,
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    current: Range<u64>,
+    prefetch: Option<JoinHandle<Result<Range<u64>>>>,
+}
+
+impl CachedFileSeq {
+    /// Wraps `seq`, reserving `block_size` values at a time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `block_size` is zero.
+    pub fn new(seq: FileSeq, block_size: u64) -> Self {
+        assert!(block_size > 0, "block size must be greater than zero");
+        Self {
+            seq: Arc::new(seq),
+            block_size,
+            state: Mutex::new(State {
+                current: 0..0,
+                prefetch: None,
+            }),
+        }
+    }
+
+    /// Returns the next value.
+    ///
+    /// Only blocks when the current block is used up and the next one hasn't
+    /// been reserved yet. Errors of a background reservation are returned
+    /// here, the following call tries again.
+    pub fn next(&self) -> Result<u64> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+
+        if state.current.is_empty() {
+            state.current = match state.prefetch.take() {
+                Some(prefetch) => prefetch.join().expect("block reservation panicked")?,
+                None => self.seq.reserve(self.block_size)?,
+            };
+        }
+        // A saturated sequence has no values left to reserve
+        let value = state.current.next().ok_or(FileSeqError::Overflow)?;
+
+        let remaining = state.current.end - state.current.start;
+        if state.prefetch.is_none() && remaining <= self.block_size / 2 {
+            let seq = Arc::clone(&self.seq);
+            let block_size = self.block_size;
+            state.prefetch = Some(thread::spawn(move || seq.reserve(block_size)));
+        }
+
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::sync::Arc;
+    use std::thread;
+
+    use crate::tests::tmpdir;
+    use crate::{CachedFileSeq, FileSeq, FileSeqError, OverflowPolicy};
+
+    #[test]
+    fn should_hand_out_contiguous_values() {
+        let dir = tmpdir();
+        let seq = CachedFileSeq::new(FileSeq::new(&dir, 1).unwrap(), 10);
+        for expected in 1..=35 {
+            assert_eq!(expected, seq.next().unwrap());
+        }
+        drop(seq);
+        // The fourth block was reserved, the fifth is being prefetched
+        let value = FileSeq::new(&dir, 1).unwrap().value().unwrap();
+        assert!(value == 41 || value == 51, "unexpected value {}", value);
+    }
+
+    #[test]
+    fn should_skip_values_lost_with_previous_instance() {
+        let dir = tmpdir();
+        let seq = CachedFileSeq::new(FileSeq::new(&dir, 1).unwrap(), 10);
+        assert_eq!(1, seq.next().unwrap());
+        drop(seq);
+        let seq = CachedFileSeq::new(FileSeq::new(&dir, 1).unwrap(), 10);
+        assert_eq!(11, seq.next().unwrap());
+    }
+
+    #[test]
+    fn should_hand_out_unique_values_across_threads() {
+        let dir = tmpdir();
+        let seq = Arc::new(CachedFileSeq::new(FileSeq::new(&dir, 1).unwrap(), 7));
+
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let seq = Arc::clone(&seq);
+                thread::spawn(move || (0..100).map(|_| seq.next().unwrap()).collect::<Vec<_>>())
+            })
+            .collect();
+
+        let mut values = HashSet::new();
+        for handle in handles {
+            for value in handle.join().unwrap() {
+                assert!(values.insert(value), "{} was handed out twice", value);
+            }
+        }
+        assert_eq!(800, values.len());
+    }
+
+    #[test]
+    fn should_fail_when_saturated() {
+        let dir = tmpdir();
+        let seq = FileSeq::new(&dir, u64::MAX - 2)
+            .unwrap()
+            .with_overflow_policy(OverflowPolicy::Saturate)
+            .unwrap();
+        let seq = CachedFileSeq::new(seq, 10);
+        assert_eq!(u64::MAX - 2, seq.next().unwrap());
+        assert_eq!(u64::MAX - 1, seq.next().unwrap());
+        assert!(matches!(seq.next(), Err(FileSeqError::Overflow)));
+    }
+}
@@ -0,0 +1,81 @@
+use std::fs::File;
+use std::path::Path;
+
+/// How hard a write tries to reach stable storage before returning.
+///
+/// Without syncing, a power loss can bring the sequence back with an older
+/// value or an empty file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Durability {
+    /// Leaves flushing to the operating system.
+    None,
+    /// Syncs the contents of every written file.
+    File,
+    /// Syncs written files and the store directory, so renames and newly
+    /// created files are durable too.
+    #[default]
+    FileAndDir,
+}
+
+impl Durability {
+    pub(crate) fn sync_file(self, file: &File) -> std::io::Result<()> {
+        if self >= Durability::File {
+            file.sync_all()?;
+        }
+        Ok(())
+    }
+
+    /// Syncs the contents of `file` but not its metadata, like `fdatasync`.
+    pub(crate) fn sync_data(self, file: &File) -> std::io::Result<()> {
+        if self >= Durability::File {
+            file.sync_data()?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn sync_dir<P: AsRef<Path>>(self, dir: P) -> std::io::Result<()> {
+        if self >= Durability::FileAndDir {
+            sync_dir(dir.as_ref())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl Durability {
+    pub(crate) async fn sync_file_async(self, file: &tokio::fs::File) -> std::io::Result<()> {
+        if self >= Durability::File {
+            file.sync_all().await?;
+        }
+        Ok(())
+    }
+
+    pub(crate) async fn sync_dir_async<P: AsRef<Path>>(self, dir: P) -> std::io::Result<()> {
+        if self >= Durability::FileAndDir {
+            sync_dir_async(dir.as_ref()).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> std::io::Result<()> {
+    File::open(dir)?.sync_all()
+}
+
+// Directories can't be opened as files on other platforms, renames are
+// expected to be durable there once the call returns.
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+#[cfg(all(unix, feature = "tokio"))]
+async fn sync_dir_async(dir: &Path) -> std::io::Result<()> {
+    tokio::fs::File::open(dir).await?.sync_all().await
+}
+
+#[cfg(all(not(unix), feature = "tokio"))]
+async fn sync_dir_async(_dir: &Path) -> std::io::Result<()> {
+    Ok(())
+}
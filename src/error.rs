@@ -0,0 +1,87 @@
+use std::error::Error;
+use std::fmt;
+
+/// Errors returned by sequence operations.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum FileSeqError {
+    /// Reading or writing the store failed.
+    Io(std::io::Error),
+    /// No version of the sequence holds a valid value.
+    Corrupted,
+    /// The sequence files don't exist, usually because the sequence was deleted.
+    NotFound,
+    /// The sequence was to be created, but it already exists.
+    AlreadyExists,
+    /// The operation would move the sequence past its bounds or the range of
+    /// its value type.
+    Overflow,
+    /// The value is outside of the bounds of the sequence.
+    OutOfBounds,
+    /// The lock is held by another thread or process and `LockMode::Try` was used.
+    Locked,
+    /// The lock couldn't be acquired within the `LockMode::Timeout` duration.
+    LockTimeout,
+    /// The sequence name can't be used as part of a file name.
+    InvalidName(String),
+    /// The sequence configuration is inconsistent.
+    InvalidConfig(String),
+}
+
+/// Result of sequence operations.
+pub type Result<T> = std::result::Result<T, FileSeqError>;
+
+impl fmt::Display for FileSeqError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FileSeqError::Io(e) => write!(f, "sequence I/O error: {}", e),
+            FileSeqError::Corrupted => {
+                f.write_str("no version of the sequence holds a valid value")
+            }
+            FileSeqError::NotFound => f.write_str("sequence does not exist"),
+            FileSeqError::AlreadyExists => f.write_str("sequence already exists"),
+            FileSeqError::Overflow => f.write_str("sequence value overflowed"),
+            FileSeqError::OutOfBounds => f.write_str("value is out of the sequence bounds"),
+            FileSeqError::Locked => f.write_str("sequence is locked"),
+            FileSeqError::LockTimeout => f.write_str("timed out waiting for the sequence lock"),
+            FileSeqError::InvalidName(name) => write!(f, "invalid sequence name {:?}", name),
+            FileSeqError::InvalidConfig(msg) => {
+                write!(f, "invalid sequence configuration: {}", msg)
+            }
+        }
+    }
+}
+
+impl Error for FileSeqError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            FileSeqError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for FileSeqError {
+    fn from(e: std::io::Error) -> Self {
+        FileSeqError::Io(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use crate::FileSeqError;
+
+    #[test]
+    fn should_keep_io_error_as_source() {
+        let err = FileSeqError::from(std::io::Error::other("disk on fire"));
+        assert!(matches!(err, FileSeqError::Io(_)));
+        assert_eq!("disk on fire", err.source().unwrap().to_string());
+    }
+
+    #[test]
+    fn should_have_no_source_for_other_errors() {
+        assert!(FileSeqError::Corrupted.source().is_none());
+    }
+}
@@ -0,0 +1,164 @@
+//! The files of a sequence and how its value is recovered from them.
+
+use std::fs;
+use std::io::{self, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use log::warn;
+
+use crate::durability::Durability;
+use crate::error::{FileSeqError, Result};
+use crate::record;
+use crate::value::SeqValue;
+
+/// Paths of the files backing a sequence.
+#[derive(Debug, Clone)]
+pub(crate) struct SeqFiles {
+    pub(crate) store_dir: PathBuf,
+    pub(crate) name: String,
+    /// Where the next latest value is written before it's renamed into place.
+    pub(crate) tmp_path: PathBuf,
+    /// Where a [`RingStorage`](crate::RingStorage) keeps every version.
+    pub(crate) ring_path: PathBuf,
+    pub(crate) lock_path: PathBuf,
+    /// Settings every process opening the sequence has to agree on.
+    pub(crate) meta_path: PathBuf,
+    pub(crate) meta_tmp_path: PathBuf,
+}
+
+impl SeqFiles {
+    pub(crate) fn new<P: AsRef<Path>>(store_dir: P, name: &str) -> Self {
+        let store_dir = store_dir.as_ref().to_path_buf();
+        Self {
+            tmp_path: store_dir.join(format!("{}.seq.tmp", name)),
+            ring_path: store_dir.join(format!("{}.ring", name)),
+            lock_path: store_dir.join(format!("{}.lock", name)),
+            meta_path: store_dir.join(format!("{}.meta", name)),
+            meta_tmp_path: store_dir.join(format!("{}.meta.tmp", name)),
+            name: name.to_string(),
+            store_dir,
+        }
+    }
+
+    /// Returns the path of version `n`, numbered from 1 for the oldest.
+    pub(crate) fn version_path(&self, n: u32) -> PathBuf {
+        self.store_dir.join(format!("{}_{}.seq", self.name, n))
+    }
+}
+
+/// Creates files with the given permissions, where supported.
+#[cfg(unix)]
+pub(crate) fn set_file_mode(options: &mut fs::OpenOptions, file_mode: Option<u32>) {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    if let Some(file_mode) = file_mode {
+        options.mode(file_mode);
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn set_file_mode(_options: &mut fs::OpenOptions, _file_mode: Option<u32>) {}
+
+/// Replaces the contents of the file at `path`.
+///
+/// The contents are written to a temporary file next to it first, so a crash
+/// leaves either the old or the new contents behind.
+pub(crate) fn replace(
+    path: &Path,
+    contents: &[u8],
+    durability: Durability,
+    file_mode: Option<u32>,
+) -> io::Result<()> {
+    let mut tmp_path = path.as_os_str().to_os_string();
+    tmp_path.push(".tmp");
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    set_file_mode(&mut options, file_mode);
+    let mut f = options.open(&tmp_path)?;
+    f.write_all(contents)?;
+    durability.sync_file(&f)?;
+    drop(f);
+
+    fs::rename(&tmp_path, path)?;
+    let dir = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+    durability.sync_dir(dir)
+}
+
+/// What a sequence file holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum FileState<T> {
+    Missing,
+    Corrupted,
+    Valid(T),
+}
+
+impl<T: SeqValue> FileState<T> {
+    pub(crate) fn read<P: AsRef<Path>>(path: P) -> Self {
+        Self::from_contents(fs::read(path.as_ref()))
+    }
+
+    /// Interprets the result of reading a whole sequence file.
+    pub(crate) fn from_contents(contents: io::Result<Vec<u8>>) -> Self {
+        match contents {
+            Ok(bytes) => match record::decode(&bytes) {
+                Ok(value) => FileState::Valid(value),
+                Err(_) => FileState::Corrupted,
+            },
+            Err(e) if e.kind() == ErrorKind::NotFound => FileState::Missing,
+            Err(_) => FileState::Corrupted,
+        }
+    }
+}
+
+/// The value recovered from the sequence files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Recovered<T> {
+    pub(crate) value: T,
+    /// Index of the version the value was read from.
+    pub(crate) version: usize,
+    /// Indexes of the corrupted versions newer than that, which should be
+    /// removed.
+    pub(crate) discard: Vec<usize>,
+}
+
+/// Decides which value the sequence holds given the state of its versions,
+/// oldest first.
+pub(crate) fn recover<T: Copy>(versions: &[FileState<T>]) -> Result<Recovered<T>> {
+    // Files are renamed into place only once they're complete and carry a
+    // checksum, so the newest valid one always holds the newest value, even
+    // when it's smaller than older ones because the sequence wrapped.
+    let newest_valid = versions
+        .iter()
+        .enumerate()
+        .rev()
+        .find_map(|(i, state)| match state {
+            FileState::Valid(value) => Some((i, *value)),
+            _ => None,
+        });
+    let (version, value) = match newest_valid {
+        Some(newest_valid) => newest_valid,
+        None if versions
+            .iter()
+            .all(|state| matches!(state, FileState::Missing)) =>
+        {
+            return Err(FileSeqError::NotFound)
+        }
+        None => return Err(FileSeqError::Corrupted),
+    };
+
+    let discard: Vec<usize> = (version + 1..versions.len())
+        .filter(|&i| matches!(versions[i], FileState::Corrupted))
+        .collect();
+    if !discard.is_empty() {
+        warn!("Latest sequence file is corrupted, using an older version.");
+    }
+    Ok(Recovered {
+        value,
+        version,
+        discard,
+    })
+}
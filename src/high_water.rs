@@ -0,0 +1,90 @@
+//! A sidecar file remembering the highest value a sequence ever stored.
+
+use std::path::{Path, PathBuf};
+
+use crate::durability::Durability;
+use crate::error::{FileSeqError, Result};
+use crate::files::{self, FileState};
+use crate::record::Codec;
+use crate::value::SeqValue;
+
+/// The high-water mark file of a sequence.
+#[derive(Debug, Clone)]
+pub(crate) struct HighWaterMark {
+    pub(crate) path: PathBuf,
+}
+
+impl HighWaterMark {
+    pub(crate) fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Returns the recorded mark, `None` if nothing was recorded yet.
+    pub(crate) fn read<T: SeqValue>(&self) -> Result<Option<T>> {
+        match FileState::read(&self.path) {
+            FileState::Missing => Ok(None),
+            FileState::Corrupted => Err(FileSeqError::Corrupted),
+            FileState::Valid(value) => Ok(Some(value)),
+        }
+    }
+
+    /// Records `value` if it's above the current mark.
+    ///
+    /// A corrupted mark is replaced, the sequence files are the source of
+    /// truth as long as they exist.
+    pub(crate) fn raise<T: SeqValue>(
+        &self,
+        value: T,
+        codec: Codec,
+        durability: Durability,
+        file_mode: Option<u32>,
+    ) -> Result<()> {
+        if let FileState::Valid(mark) = FileState::<T>::read(&self.path) {
+            if mark >= value {
+                return Ok(());
+            }
+        }
+
+        Ok(files::replace(
+            &self.path,
+            &codec.encode(value),
+            durability,
+            file_mode,
+        )?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::durability::Durability;
+    use crate::high_water::HighWaterMark;
+    use crate::record::Codec;
+    use crate::tests::tmpdir;
+    use crate::FileSeqError;
+
+    #[test]
+    fn should_only_raise_mark() {
+        let mark = HighWaterMark::new(tmpdir().join("seq.hwm"));
+        assert_eq!(None, mark.read::<u64>().unwrap());
+        mark.raise(10u64, Codec::default(), Durability::default(), None)
+            .unwrap();
+        mark.raise(5u64, Codec::default(), Durability::default(), None)
+            .unwrap();
+        assert_eq!(Some(10u64), mark.read().unwrap());
+        mark.raise(11u64, Codec::default(), Durability::default(), None)
+            .unwrap();
+        assert_eq!(Some(11u64), mark.read().unwrap());
+    }
+
+    #[test]
+    fn should_replace_corrupted_mark() {
+        let mark = HighWaterMark::new(tmpdir().join("seq.hwm"));
+        std::fs::write(&mark.path, [0xff; 3]).unwrap();
+        assert!(matches!(mark.read::<u64>(), Err(FileSeqError::Corrupted)));
+        mark.raise(1u64, Codec::default(), Durability::default(), None)
+            .unwrap();
+        assert_eq!(Some(1u64), mark.read().unwrap());
+    }
+}
@@ -1,7 +1,8 @@
 //! Fail-safe file sequence
 //!
-//! Works by versioning values of sequences and throwing away all versions,
-//! but the current and the previous one.
+//! Works by versioning values of sequences and throwing away all versions
+//! but the most recent ones, two by default, see
+//! [`FileSeqBuilder::versions`].
 //!
 //! Inspired by [this Java implementation](https://commons.apache.org/proper/commons-transaction/apidocs/org/apache/commons/transaction/file/FileSequence.html)
 //!
@@ -12,6 +13,7 @@
 //! use std::path::Path;
 //!
 //! let dir = Path::new("/tmp/example");
+//! # let _ = std::fs::remove_dir_all(&dir);
 //! let initial_value = 1;
 //!
 //! let seq = FileSeq::new(&dir, initial_value).unwrap();
@@ -26,46 +28,442 @@
 //! assert_eq!(initial_value + 1, seq.get_and_increment(1).unwrap());
 //! assert_eq!(initial_value + 2, seq.value().unwrap());
 //! ```
+//!
+//! # Value types
+//!
+//! Sequences hold `u64` values by default, [`FileSeq::new_typed`] opens
+//! sequences of any other [`SeqValue`] type.
+//!
+//! # Concurrency
+//!
+//! `FileSeq` is `Send` and `Sync`. Every operation holds an in-process mutex
+//! and an advisory lock on a `.lock` file in the store directory, so several
+//! threads and processes can safely share the same sequence.
+//! See [`LockMode`] for how to wait for those locks.
+//!
+//! With the `tokio` feature, `AsyncFileSeq` offers the same operations
+//! without blocking the executor.
+//!
+//! # Durability
+//!
+//! By default every write syncs the written file and the store directory
+//! before returning. See [`Durability`] to trade that for speed.
+//!
+//! Values are stored with a checksum, a file that fails it is treated as
+//! corrupted and the backup is used instead.
+//!
+//! With [`Codec::Text`] the files hold the value in decimal, so they can be
+//! inspected with `cat`. The `convert` example rewrites a whole store in
+//! either format.
+//!
+//! # Storage
+//!
+//! Sequences are kept in files of the store directory by [`FsStorage`].
+//! [`FileSeqBuilder::build_with_storage`] keeps them in any other
+//! [`SeqStorage`], e.g. [`MemoryStorage`] for tests that shouldn't touch the
+//! disk, or [`RingStorage`] to keep every version in the slots of a single
+//! file.
 
-use std::fs;
-use std::io::{Error, ErrorKind, Read};
-use std::path::{Path, PathBuf};
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Mutex;
 
 use log::warn;
 
+#[cfg(feature = "tokio")]
+pub use crate::async_seq::AsyncFileSeq;
+pub use crate::builder::{FileSeqBuilder, OpenMode};
+pub use crate::cached::CachedFileSeq;
+pub use crate::durability::Durability;
+pub use crate::error::{FileSeqError, Result};
+pub use crate::lock::LockMode;
+pub use crate::metadata::Metadata;
+pub use crate::overflow::OverflowPolicy;
+pub use crate::read_only::ReadOnlyFileSeq;
+pub use crate::record::Codec;
+pub use crate::ring::RingStorage;
+pub use crate::storage::{FsStorage, Layout, MemoryStorage, SeqStorage, Slot};
+pub use crate::store::SeqStore;
+pub use crate::value::SeqValue;
+pub use crate::verify::{FileReport, FileStatus, Skip, VerifyReport, Version};
+
+use crate::files::FileState;
+use crate::high_water::HighWaterMark;
+use crate::metadata::Requested;
+
+#[cfg(feature = "tokio")]
+mod async_seq;
+mod builder;
+mod cached;
+mod durability;
+mod error;
+mod files;
+mod high_water;
+mod lock;
+mod metadata;
+mod overflow;
+mod read_only;
+mod record;
+mod ring;
+mod storage;
+mod store;
+mod value;
+mod verify;
+
 #[derive(Debug)]
-pub struct FileSeq {
-    path_1: PathBuf,
-    path_2: PathBuf,
+pub struct FileSeq<T = u64, S = FsStorage> {
+    storage: S,
+    lock_mode: LockMode,
+    durability: Durability,
+    overflow_policy: OverflowPolicy<T>,
+    metadata: Metadata<T>,
+    file_mode: Option<u32>,
+    high_water_mark: Option<HighWaterMark>,
+    codec: Codec,
+    repair_on_read: bool,
+    recovery_gap: Option<T>,
+    last_skip: Mutex<Option<Skip<T>>>,
+    mutex: Mutex<()>,
 }
 
 impl FileSeq {
-    pub fn new<P: AsRef<Path>>(store_dir: P, initial_value: u64) -> std::io::Result<Self> {
-        let store_path = store_dir.as_ref();
+    pub fn new<P: AsRef<Path>>(store_dir: P, initial_value: u64) -> Result<Self> {
+        Self::new_named(store_dir, "", initial_value)
+    }
+
+    /// Opens the existing sequence in `store_dir`.
+    ///
+    /// Unlike [`FileSeq::new`], this never creates the sequence. If the store
+    /// was wiped or the wrong directory is mounted, it fails with
+    /// [`FileSeqError::NotFound`] instead of restarting from an initial value.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use file_seq::{FileSeq, FileSeqError};
+    /// use std::path::Path;
+    ///
+    /// let dir = Path::new("/tmp/example_open");
+    /// # let _ = std::fs::remove_dir_all(&dir);
+    ///
+    /// assert!(matches!(FileSeq::open(&dir), Err(FileSeqError::NotFound)));
+    ///
+    /// FileSeq::new(&dir, 1).unwrap();
+    /// assert_eq!(1, FileSeq::open(&dir).unwrap().value().unwrap());
+    /// ```
+    pub fn open<P: AsRef<Path>>(store_dir: P) -> Result<Self> {
+        Self::open_typed(store_dir)
+    }
+
+    /// Opens the existing sequence in `store_dir` for reading only.
+    ///
+    /// See [`ReadOnlyFileSeq`].
+    pub fn open_read_only<P: AsRef<Path>>(store_dir: P) -> Result<ReadOnlyFileSeq> {
+        Self::open_read_only_typed(store_dir)
+    }
 
-        fs::create_dir_all(store_path)?;
-        let store_path_buf = store_path.to_path_buf();
-        let path_1 = store_path_buf.join("_1.seq");
-        let path_2 = store_path_buf.join("_2.seq");
+    /// Returns a builder to configure the sequence in `store_dir`.
+    ///
+    /// See [`FileSeqBuilder`] for the available settings.
+    pub fn builder<P: AsRef<Path>>(store_dir: P) -> FileSeqBuilder {
+        FileSeqBuilder::new(store_dir)
+    }
+}
 
-        let seq = Self { path_1, path_2 };
+impl<T: SeqValue> FileSeq<T> {
+    /// Opens the sequence in `store_dir`, creating it with `initial_value` if
+    /// necessary, for value types other than `u64`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use file_seq::FileSeq;
+    /// use std::path::Path;
+    ///
+    /// let dir = Path::new("/tmp/example_new_typed");
+    /// # let _ = std::fs::remove_dir_all(&dir);
+    ///
+    /// let seq = FileSeq::new_typed(&dir, -1i64).unwrap();
+    ///
+    /// assert_eq!(0, seq.increment_and_get(1).unwrap());
+    /// ```
+    pub fn new_typed<P: AsRef<Path>>(store_dir: P, initial_value: T) -> Result<Self> {
+        Self::new_named(store_dir, "", initial_value)
+    }
 
-        seq.initialize_if_necessary(initial_value)?;
+    /// Same as [`FileSeq::open`], for value types other than `u64`.
+    pub fn open_typed<P: AsRef<Path>>(store_dir: P) -> Result<Self> {
+        FileSeqBuilder::new_typed(store_dir)
+            .open_mode(OpenMode::OpenExisting)
+            .build()
+    }
+
+    /// Same as [`FileSeq::open_read_only`], for value types other than `u64`.
+    pub fn open_read_only_typed<P: AsRef<Path>>(store_dir: P) -> Result<ReadOnlyFileSeq<T>> {
+        ReadOnlyFileSeq::open(store_dir, "")
+    }
 
+    /// Opens the sequence stored under `name` in `store_dir`, creating it with
+    /// `initial_value` if necessary.
+    pub(crate) fn new_named<P: AsRef<Path>>(
+        store_dir: P,
+        name: &str,
+        initial_value: T,
+    ) -> Result<Self> {
+        FileSeqBuilder::new_typed(store_dir)
+            .name(name)
+            .initial_value(initial_value)
+            .build()
+    }
+
+    /// Returns the sequence stored under `name` without changing the store,
+    /// with the stored metadata if it can be read.
+    ///
+    /// Fails if the sequence is stored in another [`Layout`].
+    pub(crate) fn unopened_in<P: AsRef<Path>>(store_dir: P, name: &str) -> Result<Self> {
+        let mut seq = Self::unopened(FsStorage::new(store_dir, name));
+        match Metadata::read(&seq.storage) {
+            Ok(Some(metadata)) => {
+                metadata.check_layout(Layout::Files)?;
+                seq.metadata = metadata;
+            }
+            _ => seq.metadata.versions = metadata::found_versions(&seq.storage),
+        }
         Ok(seq)
     }
+}
+
+impl<T: SeqValue, S: SeqStorage> FileSeq<T, S> {
+    /// Returns the sequence kept in `storage` without touching it.
+    pub(crate) fn unopened(storage: S) -> Self {
+        Self {
+            storage,
+            lock_mode: LockMode::default(),
+            durability: Durability::default(),
+            overflow_policy: OverflowPolicy::default(),
+            metadata: Requested::default().metadata(None),
+            file_mode: None,
+            high_water_mark: None,
+            codec: Codec::default(),
+            repair_on_read: true,
+            recovery_gap: None,
+            last_skip: Mutex::new(None),
+            mutex: Mutex::new(()),
+        }
+    }
+
+    /// Returns whether any version of the value exists.
+    pub(crate) fn exists(&self) -> bool {
+        (1..=self.metadata.versions).any(|n| self.storage.exists(Slot::Version(n)))
+    }
 
-    fn initialize_if_necessary(&self, initial_value: u64) -> std::io::Result<()> {
-        if fs::metadata(&self.path_1).is_ok() || fs::metadata(&self.path_2).is_ok() {
-            Ok(())
+    /// Sets how operations wait for the lock shared with other processes.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use file_seq::{FileSeq, LockMode};
+    /// use std::path::Path;
+    /// use std::time::Duration;
+    ///
+    /// let dir = Path::new("/tmp/example_lock_mode");
+    /// # let _ = std::fs::remove_dir_all(&dir);
+    ///
+    /// let seq = FileSeq::new(&dir, 1)
+    ///     .unwrap()
+    ///     .with_lock_mode(LockMode::Timeout(Duration::from_secs(1)));
+    ///
+    /// assert_eq!(1, seq.get_and_increment(1).unwrap());
+    /// ```
+    pub fn with_lock_mode(mut self, lock_mode: LockMode) -> Self {
+        self.lock_mode = lock_mode;
+        self
+    }
+
+    /// Sets how far writes go to make the new value survive a power loss.
+    ///
+    /// Defaults to [`Durability::FileAndDir`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use file_seq::{Durability, FileSeq};
+    /// use std::path::Path;
+    ///
+    /// let dir = Path::new("/tmp/example_durability");
+    /// # let _ = std::fs::remove_dir_all(&dir);
+    ///
+    /// let seq = FileSeq::new(&dir, 1)
+    ///     .unwrap()
+    ///     .with_durability(Durability::File);
+    ///
+    /// assert_eq!(2, seq.increment_and_get(1).unwrap());
+    /// ```
+    pub fn with_durability(mut self, durability: Durability) -> Self {
+        self.durability = durability;
+        self
+    }
+
+    /// Sets what increments do when the value would overflow.
+    ///
+    /// Defaults to the policy stored with the sequence. Unlike
+    /// [`FileSeqBuilder::overflow_policy`], this only affects this handle and
+    /// isn't stored. Fails with [`FileSeqError::InvalidConfig`] if the policy
+    /// would continue from a value outside the stored bounds.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use file_seq::{FileSeq, OverflowPolicy};
+    /// use std::path::Path;
+    ///
+    /// let dir = Path::new("/tmp/example_overflow_policy");
+    /// # let _ = std::fs::remove_dir_all(&dir);
+    ///
+    /// let seq = FileSeq::new(&dir, u64::MAX)
+    ///     .unwrap()
+    ///     .with_overflow_policy(OverflowPolicy::WrapTo(1))
+    ///     .unwrap();
+    ///
+    /// assert_eq!(1, seq.increment_and_get(1).unwrap());
+    /// ```
+    pub fn with_overflow_policy(mut self, overflow_policy: OverflowPolicy<T>) -> Result<Self> {
+        self.overflow_policy = overflow_policy.check(self.metadata.bounds())?;
+        Ok(self)
+    }
+
+    /// Sets the format new values are written in.
+    ///
+    /// Defaults to [`Codec::Binary`]. Files are read in whatever format they
+    /// were written, use [`FileSeq::convert`] to rewrite existing files.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use file_seq::{Codec, FileSeq};
+    /// use std::path::Path;
+    ///
+    /// let dir = Path::new("/tmp/example_codec");
+    /// # let _ = std::fs::remove_dir_all(&dir);
+    ///
+    /// let seq = FileSeq::new(&dir, 1).unwrap().with_codec(Codec::Text);
+    /// seq.increment_and_get(41).unwrap();
+    ///
+    /// let text = std::fs::read_to_string(dir.join("_2.seq")).unwrap();
+    /// assert_eq!(Some("42"), text.lines().next());
+    /// ```
+    pub fn with_codec(mut self, codec: Codec) -> Self {
+        self.codec = codec;
+        self
+    }
+
+    fn lock(&self) -> Result<lock::SeqGuard<'_>> {
+        lock::acquire_all(
+            self.storage.shared_mutex().unwrap_or(&self.mutex),
+            self.storage.lock_path(),
+            self.lock_mode,
+            self.file_mode,
+        )
+    }
+
+    /// Makes sure the sequence exists as required by `open_mode` and adopts
+    /// its stored settings.
+    ///
+    /// A created sequence starts at `initial_value`, raised to `floor` and
+    /// above the high-water mark if necessary.
+    fn initialize(
+        &mut self,
+        open_mode: OpenMode,
+        initial_value: Option<T>,
+        floor: Option<T>,
+        requested: Requested<T>,
+    ) -> Result<()> {
+        // Not self.lock(), which would borrow all of self while the settings
+        // are adopted below
+        let _guard = lock::acquire_all(
+            self.storage.shared_mutex().unwrap_or(&self.mutex),
+            self.storage.lock_path(),
+            self.lock_mode,
+            self.file_mode,
+        )?;
+        let stored = Metadata::read(&self.storage)?;
+        if let Some(stored) = &stored {
+            stored.check_layout(self.storage.layout())?;
+            requested.check(stored)?;
+        }
+        self.metadata = stored.clone().unwrap_or_else(|| Metadata {
+            layout: self.storage.layout(),
+            ..requested.metadata(None)
+        });
+        if stored.is_none() {
+            // Sequences created before metadata was stored have two versions,
+            // which any number of versions reads correctly, but the metadata
+            // of one keeping more may have been lost
+            let found = metadata::found_versions(&self.storage);
+            requested.check_found_versions(found)?;
+            self.metadata.versions = self.metadata.versions.max(found);
+        }
+        self.overflow_policy = self.metadata.overflow_policy;
+
+        let exists = self.exists();
+        match (open_mode, exists) {
+            (OpenMode::CreateNew, true) => return Err(FileSeqError::AlreadyExists),
+            (OpenMode::OpenExisting, false) => return Err(FileSeqError::NotFound),
+            _ => {}
+        }
+
+        let value = if exists {
+            None
         } else {
-            self.write(initial_value)
+            Some(self.initial_value(initial_value, floor)?)
+        };
+        if stored.is_none() {
+            // Also records the settings of sequences created before metadata
+            // was stored, without their unknown initial value
+            self.metadata.initial_value = value;
+            self.metadata.write(&self.storage, self.durability)?;
+        }
+        match value {
+            Some(value) => self.write(value),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the value a new sequence starts at.
+    fn initial_value(&self, initial_value: Option<T>, floor: Option<T>) -> Result<T> {
+        let bounds = self.metadata.bounds();
+        let initial_value = match initial_value {
+            Some(value) => bounds.check(value)?,
+            None => T::ZERO.max(bounds.min).min(bounds.max),
+        };
+        if floor.is_some_and(|floor| !bounds.contains(floor)) {
+            return Err(FileSeqError::InvalidConfig(
+                "floor is out of bounds".to_string(),
+            ));
+        }
+
+        let mut value = initial_value.max(floor.unwrap_or(initial_value));
+        if let Some(mark) = &self.high_water_mark {
+            if let Some(mark) = mark.read()? {
+                // The mark itself may have been handed out already
+                let above = self.overflow_policy.add(mark, self.metadata.step, bounds)?;
+                value = value.max(above);
+            }
+        }
+        if value != initial_value {
+            warn!(
+                "Creating sequence with {} instead of {} to stay above previous values.",
+                value, initial_value
+            );
         }
+        Ok(value)
     }
 
     /// Deletes this sequence
     ///
-    /// Once deleted, the sequence must be recreated
+    /// Once deleted, the sequence must be recreated. A high-water mark set
+    /// with [`FileSeqBuilder::high_water_mark`] is kept. Nothing is deleted
+    /// if the lock can't be acquired.
     ///
     /// # Example
     ///
@@ -74,6 +472,7 @@ impl FileSeq {
     /// use std::path::Path;
     ///
     /// let dir = Path::new("/tmp/example_delete");
+    /// # let _ = std::fs::remove_dir_all(&dir);
     /// let initial_value = 1;
     ///
     /// let seq = FileSeq::new(&dir, initial_value).unwrap();
@@ -81,15 +480,21 @@ impl FileSeq {
     /// // Get current value
     /// assert_eq!(initial_value, seq.value().unwrap());
     ///
-    /// seq.delete();
+    /// seq.delete().unwrap();
     ///
     /// // Attempts to read the sequence after it's deleted returns an error
     /// assert_eq!(seq.value().is_err(), true)
     /// ```
-    pub fn delete(&self) -> () {
+    pub fn delete(&self) -> Result<()> {
+        // The lock file is kept, removing it would let two processes
+        // lock different files at the same path.
+        let _guard = self.lock()?;
         // The files might not exist already
-        let _ = fs::remove_file(&self.path_1);
-        let _ = fs::remove_file(&self.path_2);
+        let versions = (1..=self.metadata.versions).map(Slot::Version);
+        for slot in versions.chain([Slot::Pending, Slot::Metadata, Slot::PendingMetadata]) {
+            let _ = self.storage.remove(slot);
+        }
+        Ok(())
     }
 
     /// Returns the current value of the sequence and then increments it.
@@ -101,6 +506,7 @@ impl FileSeq {
     /// use std::path::Path;
     ///
     /// let dir = Path::new("/tmp/example_get_and_increment");
+    /// # let _ = std::fs::remove_dir_all(&dir);
     /// let initial_value = 1;
     ///
     /// let seq = FileSeq::new(&dir, initial_value).unwrap();
@@ -109,12 +515,41 @@ impl FileSeq {
     /// assert_eq!(initial_value + 1, seq.value().unwrap());
     ///
     /// ```
-    pub fn get_and_increment(&self, increment: u64) -> std::io::Result<u64> {
-        let value = self.read()?;
-        self.write(value + increment)?;
+    pub fn get_and_increment(&self, increment: T) -> Result<T> {
+        let (value, _) = self.update(|value| {
+            self.overflow_policy
+                .add(value, increment, self.metadata.bounds())
+        })?;
         Ok(value)
     }
 
+    /// Returns the current value of the sequence and then increments it by
+    /// the step of the sequence, one unless set with
+    /// [`FileSeqBuilder::step`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use file_seq::FileSeq;
+    /// use std::path::Path;
+    ///
+    /// let dir = Path::new("/tmp/example_next");
+    /// # let _ = std::fs::remove_dir_all(&dir);
+    ///
+    /// let seq = FileSeq::builder(&dir)
+    ///     .initial_value(10)
+    ///     .step(10)
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// assert_eq!(10, seq.next().unwrap());
+    /// assert_eq!(20, seq.next().unwrap());
+    ///
+    /// ```
+    pub fn next(&self) -> Result<T> {
+        self.get_and_increment(self.metadata.step)
+    }
+
     /// Increments the sequence and return the value.
     ///
     /// # Example
@@ -124,6 +559,7 @@ impl FileSeq {
     /// use std::path::Path;
     ///
     /// let dir = Path::new("/tmp/example_increment_and_get");
+    /// # let _ = std::fs::remove_dir_all(&dir);
     /// let initial_value = 1;
     ///
     /// let seq = FileSeq::new(&dir, initial_value).unwrap();
@@ -132,12 +568,23 @@ impl FileSeq {
     /// assert_eq!(initial_value + 1, seq.value().unwrap());
     ///
     /// ```
-    pub fn increment_and_get(&self, increment: u64) -> std::io::Result<u64> {
-        let value = self.get_and_increment(increment)?;
-        Ok(value + increment)
+    pub fn increment_and_get(&self, increment: T) -> Result<T> {
+        let (_, next) = self.update(|value| {
+            self.overflow_policy
+                .add(value, increment, self.metadata.bounds())
+        })?;
+        Ok(next)
     }
 
-    /// Returns the current value of the sequence.
+    /// Reserves a block of `len` values at once.
+    ///
+    /// The sequence is advanced past the block with a single write, the
+    /// caller is free to hand out every value in the returned range. Values
+    /// the caller doesn't use are lost.
+    ///
+    /// With [`OverflowPolicy::Saturate`] the block can be shorter than `len`,
+    /// wrapping policies start it over instead of splitting it. A negative
+    /// `len` fails with [`FileSeqError::Overflow`].
     ///
     /// # Example
     ///
@@ -145,91 +592,378 @@ impl FileSeq {
     /// use file_seq::FileSeq;
     /// use std::path::Path;
     ///
-    /// let dir = Path::new("/tmp/example_value");
+    /// let dir = Path::new("/tmp/example_reserve");
+    /// # let _ = std::fs::remove_dir_all(&dir);
     /// let initial_value = 1;
     ///
     /// let seq = FileSeq::new(&dir, initial_value).unwrap();
     ///
-    /// assert_eq!(initial_value, seq.value().unwrap());
+    /// assert_eq!(1..101, seq.reserve(100).unwrap());
+    /// assert_eq!(101, seq.value().unwrap());
+    ///
+    /// ```
+    pub fn reserve(&self, len: T) -> Result<Range<T>> {
+        let _guard = self.lock()?;
+        let value = self.read()?;
+        let block = self
+            .overflow_policy
+            .block(value, len, self.metadata.bounds())?;
+        self.write(block.end)?;
+        Ok(block)
+    }
+
+    /// Decrements the sequence and returns the value.
+    ///
+    /// # Example
     ///
     /// ```
-    pub fn value(&self) -> std::io::Result<u64> {
-        self.read()
+    /// use file_seq::FileSeq;
+    /// use std::path::Path;
+    ///
+    /// let dir = Path::new("/tmp/example_decrement_and_get");
+    /// # let _ = std::fs::remove_dir_all(&dir);
+    /// let initial_value = 10;
+    ///
+    /// let seq = FileSeq::new(&dir, initial_value).unwrap();
+    ///
+    /// assert_eq!(initial_value - 1, seq.decrement_and_get(1).unwrap());
+    /// assert_eq!(initial_value - 1, seq.value().unwrap());
+    ///
+    /// ```
+    pub fn decrement_and_get(&self, decrement: T) -> Result<T> {
+        let (_, next) = self.update(|value| {
+            self.overflow_policy
+                .sub(value, decrement, self.metadata.bounds())
+        })?;
+        Ok(next)
     }
 
-    fn read(&self) -> std::io::Result<u64> {
-        let mut value1: Option<u64> = None;
-        if fs::metadata(&self.path_1).is_ok() {
-            let value = self.read_from_path(&self.path_1)?;
-            value1 = Some(value);
+    /// Returns the current value of the sequence and then decrements it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use file_seq::FileSeq;
+    /// use std::path::Path;
+    ///
+    /// let dir = Path::new("/tmp/example_get_and_decrement");
+    /// # let _ = std::fs::remove_dir_all(&dir);
+    /// let initial_value = 10;
+    ///
+    /// let seq = FileSeq::new(&dir, initial_value).unwrap();
+    ///
+    /// assert_eq!(initial_value, seq.get_and_decrement(1).unwrap());
+    /// assert_eq!(initial_value - 1, seq.value().unwrap());
+    ///
+    /// ```
+    pub fn get_and_decrement(&self, decrement: T) -> Result<T> {
+        let (value, _) = self.update(|value| {
+            self.overflow_policy
+                .sub(value, decrement, self.metadata.bounds())
+        })?;
+        Ok(value)
+    }
+
+    /// Sets the sequence to the given value, e.g. to reseed it after a restore.
+    ///
+    /// The current value isn't needed, so this also works when every version
+    /// is corrupted. Corrupted versions newer than a valid one are still
+    /// removed first, so the write doesn't rotate them over it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use file_seq::FileSeq;
+    /// use std::path::Path;
+    ///
+    /// let dir = Path::new("/tmp/example_set");
+    /// # let _ = std::fs::remove_dir_all(&dir);
+    ///
+    /// let seq = FileSeq::new(&dir, 1).unwrap();
+    ///
+    /// seq.set(100).unwrap();
+    /// assert_eq!(100, seq.value().unwrap());
+    ///
+    /// ```
+    pub fn set(&self, value: T) -> Result<()> {
+        self.metadata.bounds().check(value)?;
+        let _guard = self.lock()?;
+        let versions: Vec<_> = (1..=self.metadata.versions)
+            .map(|n| self.read_slot(Slot::Version(n)))
+            .collect();
+        match files::recover(&versions) {
+            Ok(recovered) => {
+                for &i in &recovered.discard {
+                    self.storage.remove(Slot::Version(i as u32 + 1))?;
+                }
+            }
+            Err(FileSeqError::Corrupted) => {}
+            Err(e) => return Err(e),
         }
+        self.write(value)
+    }
 
-        let mut value2: Option<u64> = None;
-        if fs::metadata(&self.path_2).is_ok() {
-            value2 = self.read_from_path(&self.path_2).ok();
+    /// Sets the sequence to `new` if its current value is `expected`.
+    ///
+    /// Returns whether the value was set. The check and the write happen under
+    /// the same lock, so this is atomic across threads and processes.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use file_seq::FileSeq;
+    /// use std::path::Path;
+    ///
+    /// let dir = Path::new("/tmp/example_compare_and_set");
+    /// # let _ = std::fs::remove_dir_all(&dir);
+    ///
+    /// let seq = FileSeq::new(&dir, 1).unwrap();
+    ///
+    /// assert_eq!(false, seq.compare_and_set(2, 10).unwrap());
+    /// assert_eq!(1, seq.value().unwrap());
+    ///
+    /// assert_eq!(true, seq.compare_and_set(1, 10).unwrap());
+    /// assert_eq!(10, seq.value().unwrap());
+    ///
+    /// ```
+    pub fn compare_and_set(&self, expected: T, new: T) -> Result<bool> {
+        self.metadata.bounds().check(new)?;
+        let _guard = self.lock()?;
+        if self.read()? != expected {
+            return Ok(false);
         }
+        self.write(new)?;
+        Ok(true)
+    }
 
-        match value2 {
-            Some(v2) => match value1 {
-                Some(v1) => {
-                    if v2 > v1 {
-                        Ok(v2)
-                    } else {
-                        warn!("Latest sequence value is smaller than backup, using backup.");
-                        fs::remove_file(&self.path_2).ok();
-                        Ok(v1)
-                    }
-                }
-                None => Ok(v2),
-            },
-            None => {
-                fs::remove_file(&self.path_2).ok();
-
-                match value1 {
-                    Some(v1) => Ok(v1),
-                    None => Err(Error::new(
-                        ErrorKind::InvalidData,
-                        "Looks like both backup and latest sequence files are corrupted.",
-                    )),
-                }
+    /// Rewrites the sequence files in the format of `codec`, keeping their
+    /// values.
+    ///
+    /// Also makes `codec` the format new values are written in. Corrupted
+    /// files are left as they are.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use file_seq::{Codec, FileSeq};
+    /// use std::path::Path;
+    ///
+    /// let dir = Path::new("/tmp/example_convert");
+    /// # let _ = std::fs::remove_dir_all(&dir);
+    ///
+    /// let mut seq = FileSeq::new(&dir, 1).unwrap();
+    /// seq.increment_and_get(1).unwrap();
+    /// seq.convert(Codec::Text).unwrap();
+    ///
+    /// let text = std::fs::read_to_string(dir.join("_1.seq")).unwrap();
+    /// assert_eq!(Some("1"), text.lines().next());
+    /// assert_eq!(2, seq.value().unwrap());
+    /// ```
+    pub fn convert(&mut self, codec: Codec) -> Result<()> {
+        self.codec = codec;
+        let _guard = self.lock()?;
+        for slot in (1..=self.metadata.versions).map(Slot::Version) {
+            if let FileState::Valid(value) = self.read_slot(slot) {
+                self.storage
+                    .replace(slot, &codec.encode(value), self.durability)?;
             }
         }
+        Ok(())
+    }
+
+    /// Applies `f` to the current value and stores the result, returning the
+    /// previous and the new value.
+    fn update<F: FnOnce(T) -> Result<T>>(&self, f: F) -> Result<(T, T)> {
+        let _guard = self.lock()?;
+        let value = self.read()?;
+        let next = f(value)?;
+        self.write(next)?;
+        Ok((value, next))
+    }
+
+    /// Returns the current value of the sequence.
+    ///
+    /// If the latest file is corrupted, the value is read from the backup and
+    /// the latest file is removed, unless disabled with
+    /// [`FileSeqBuilder::repair_on_read`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use file_seq::FileSeq;
+    /// use std::path::Path;
+    ///
+    /// let dir = Path::new("/tmp/example_value");
+    /// # let _ = std::fs::remove_dir_all(&dir);
+    /// let initial_value = 1;
+    ///
+    /// let seq = FileSeq::new(&dir, initial_value).unwrap();
+    ///
+    /// assert_eq!(initial_value, seq.value().unwrap());
+    ///
+    /// ```
+    pub fn value(&self) -> Result<T> {
+        // Reading can clean up a stale latest file, so it must not
+        // interleave with a write from another process.
+        let _guard = self.lock()?;
+        self.recover(self.repair_on_read)
+    }
+
+    /// Returns the last time this handle skipped ahead after recovering the
+    /// value from the backup, see [`FileSeqBuilder::recovery_gap`].
+    pub fn last_skip(&self) -> Option<Skip<T>> {
+        *self.last_skip.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Returns what was recorded about the sequence when it was created.
+    ///
+    /// See [`Metadata`].
+    pub fn metadata(&self) -> &Metadata<T> {
+        &self.metadata
+    }
+
+    /// Reports the state of the sequence files and which one the value is
+    /// read from, without changing them.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use file_seq::{FileSeq, FileStatus, Version};
+    /// use std::path::Path;
+    ///
+    /// let dir = Path::new("/tmp/example_verify");
+    /// # let _ = std::fs::remove_dir_all(&dir);
+    ///
+    /// let seq = FileSeq::new(&dir, 1).unwrap();
+    /// seq.increment_and_get(1).unwrap();
+    ///
+    /// let report = seq.verify().unwrap();
+    /// assert_eq!(FileStatus::Valid(1), report.backup.status);
+    /// assert_eq!(Some(Version::Latest), report.selected);
+    /// assert_eq!(Some(2), report.value());
+    /// ```
+    pub fn verify(&self) -> Result<VerifyReport<T>> {
+        let _guard = self.lock()?;
+        Ok(VerifyReport::read(&self.storage, self.metadata.versions))
+    }
+
+    /// Removes corrupted files newer than the newest valid one, so the value
+    /// is read from it, and the temporary file left by an interrupted write.
+    ///
+    /// Returns the report of the files before they were repaired. Fails if
+    /// no file holds a valid value, in which case nothing is removed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use file_seq::FileSeq;
+    /// use std::path::Path;
+    ///
+    /// let dir = Path::new("/tmp/example_repair");
+    /// # let _ = std::fs::remove_dir_all(&dir);
+    ///
+    /// let seq = FileSeq::new(&dir, 1).unwrap();
+    /// seq.increment_and_get(1).unwrap();
+    /// std::fs::write(dir.join("_2.seq"), "garbage").unwrap();
+    ///
+    /// assert!(seq.repair().unwrap().needs_repair());
+    /// assert!(!seq.verify().unwrap().needs_repair());
+    /// assert_eq!(1, seq.value().unwrap());
+    /// ```
+    pub fn repair(&self) -> Result<VerifyReport<T>> {
+        let _guard = self.lock()?;
+        let report = VerifyReport::read(&self.storage, self.metadata.versions);
+        self.read()?;
+        // No write is in progress while the lock is held
+        let _ = self.storage.remove(Slot::Pending);
+        Ok(report)
+    }
+
+    /// Reads the value for an update, the update replaces a corrupted latest
+    /// file anyway.
+    fn read(&self) -> Result<T> {
+        self.recover(true)
     }
 
-    fn read_from_path<P: AsRef<Path>>(&self, path: P) -> std::io::Result<u64> {
-        let mut buff = [0; 8];
-        let mut f = fs::File::open(path.as_ref())?;
-        f.read_exact(&mut buff)?;
-        let value = u64::from_be_bytes(buff);
+    /// Reads the value, removing corrupted versions newer than the one it
+    /// was read from if `repair` is set.
+    ///
+    /// Writing rotates every version to the next older one, so corrupted
+    /// versions must be removed before the next write.
+    ///
+    /// With a recovery gap, a value read from an older version is skipped
+    /// ahead, and stored if `repair` is set.
+    fn recover(&self, repair: bool) -> Result<T> {
+        let versions: Vec<_> = (1..=self.metadata.versions)
+            .map(|n| self.read_slot(Slot::Version(n)))
+            .collect();
+        let recovered = files::recover(&versions)?;
+        if repair {
+            for &i in &recovered.discard {
+                self.storage.remove(Slot::Version(i as u32 + 1)).ok();
+            }
+        }
+
+        let gap = match self.recovery_gap {
+            Some(gap) if recovered.version + 1 < versions.len() => gap,
+            _ => return Ok(recovered.value),
+        };
+        let value = self
+            .overflow_policy
+            .add(recovered.value, gap, self.metadata.bounds())?;
+        warn!(
+            "Skipping sequence from {} to {} after recovering it from an older version.",
+            recovered.value, value
+        );
+        *self.last_skip.lock().unwrap_or_else(|e| e.into_inner()) = Some(Skip {
+            from: recovered.value,
+            to: value,
+        });
+        if repair {
+            self.write(value)?;
+        }
         Ok(value)
     }
 
-    fn write(&self, value: u64) -> std::io::Result<()> {
-        if fs::metadata(&self.path_2).is_ok() {
-            fs::rename(&self.path_2, &self.path_1)?;
+    /// Stores `value` as the latest version, see
+    /// [`SeqStorage::push_version`] for how a crash is survived.
+    ///
+    /// The high-water mark is raised first, so it's never below a value
+    /// that made it to the sequence files.
+    fn write(&self, value: T) -> Result<()> {
+        if let Some(mark) = &self.high_water_mark {
+            mark.raise(value, self.codec, self.durability, self.file_mode)?;
         }
-        self.write_to_path(&self.path_2, value)
+        let contents = self.codec.encode(value);
+        Ok(self
+            .storage
+            .push_version(&contents, self.metadata.versions, self.durability)?)
     }
 
-    fn write_to_path<P: AsRef<Path>>(&self, path: P, value: u64) -> std::io::Result<()> {
-        fs::write(path.as_ref(), value.to_be_bytes())
+    fn read_slot(&self, slot: Slot) -> FileState<T> {
+        FileState::from_contents(self.storage.read(slot))
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashSet;
     use std::env;
     use std::fs;
     use std::path::PathBuf;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
 
     use rand::RngCore;
 
-    use crate::FileSeq;
+    use crate::files::FileState;
+    use crate::{Codec, Durability, FileSeq, FileSeqError, LockMode, OverflowPolicy};
 
     pub fn tmpdir() -> PathBuf {
         let p = env::temp_dir();
         let mut r = rand::thread_rng();
-        let ret = p.join(&format!("file-seq-{}", r.next_u32()));
+        let ret = p.join(format!("file-seq-{}", r.next_u32()));
         fs::create_dir(&ret).unwrap();
         ret
     }
@@ -239,7 +973,7 @@ mod tests {
         let dir = tmpdir();
         let seq = FileSeq::new(&dir, 1).unwrap();
         assert!(std::fs::metadata(dir).is_ok());
-        assert!(std::fs::metadata(seq.path_2).is_ok());
+        assert!(std::fs::metadata(seq.storage.files.version_path(2)).is_ok());
     }
 
     #[test]
@@ -247,10 +981,10 @@ mod tests {
         let dir = tmpdir();
         let seq = FileSeq::new(&dir, 1).unwrap();
         assert!(std::fs::metadata(dir).is_ok());
-        assert!(std::fs::metadata(&seq.path_2).is_ok());
-        let path_2_value = std::fs::read(&seq.path_2).unwrap();
+        assert!(std::fs::metadata(seq.storage.files.version_path(2)).is_ok());
+        let path_2_value = std::fs::read(seq.storage.files.version_path(2)).unwrap();
         seq.increment_and_get(1).unwrap();
-        let path_1_value = std::fs::read(&seq.path_1).unwrap();
+        let path_1_value = std::fs::read(seq.storage.files.version_path(1)).unwrap();
         assert_eq!(path_2_value, path_1_value);
     }
 
@@ -259,11 +993,11 @@ mod tests {
         let dir = tmpdir();
         let seq = FileSeq::new(&dir, 1).unwrap();
         assert!(std::fs::metadata(dir).is_ok());
-        assert!(std::fs::metadata(&seq.path_2).is_ok());
+        assert!(std::fs::metadata(seq.storage.files.version_path(2)).is_ok());
         seq.increment_and_get(1).unwrap();
-        seq.delete();
-        assert!(!std::fs::metadata(&seq.path_1).is_ok());
-        assert!(!std::fs::metadata(&seq.path_2).is_ok());
+        seq.delete().unwrap();
+        assert!(std::fs::metadata(seq.storage.files.version_path(1)).is_err());
+        assert!(std::fs::metadata(seq.storage.files.version_path(2)).is_err());
     }
 
     #[test]
@@ -285,4 +1019,481 @@ mod tests {
         assert_eq!(prev_value, curr_value);
         assert_eq!(curr_value + 1, seq.value().unwrap())
     }
+
+    #[test]
+    fn should_fail_to_try_lock_held_sequence() {
+        let dir = tmpdir();
+        let seq = FileSeq::new(&dir, 1).unwrap().with_lock_mode(LockMode::Try);
+        let held = fs::File::open(&seq.storage.files.lock_path).unwrap();
+        held.lock().unwrap();
+        let err = seq.get_and_increment(1).unwrap_err();
+        assert!(matches!(err, FileSeqError::Locked));
+        held.unlock().unwrap();
+        assert_eq!(1, seq.get_and_increment(1).unwrap());
+    }
+
+    #[test]
+    fn should_not_delete_without_lock() {
+        let dir = tmpdir();
+        let seq = FileSeq::new(&dir, 1).unwrap().with_lock_mode(LockMode::Try);
+        let held = fs::File::open(&seq.storage.files.lock_path).unwrap();
+        held.lock().unwrap();
+        assert!(matches!(seq.delete(), Err(FileSeqError::Locked)));
+        held.unlock().unwrap();
+        assert_eq!(1, seq.value().unwrap());
+    }
+
+    #[test]
+    fn should_time_out_waiting_for_held_lock() {
+        let dir = tmpdir();
+        let seq = FileSeq::new(&dir, 1)
+            .unwrap()
+            .with_lock_mode(LockMode::Timeout(Duration::from_millis(50)));
+        let held = fs::File::open(&seq.storage.files.lock_path).unwrap();
+        held.lock().unwrap();
+        let err = seq.increment_and_get(1).unwrap_err();
+        assert!(matches!(err, FileSeqError::LockTimeout));
+        drop(held);
+        assert_eq!(2, seq.increment_and_get(1).unwrap());
+    }
+
+    #[test]
+    fn should_be_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<FileSeq>();
+    }
+
+    #[test]
+    fn should_hand_out_unique_values_across_threads() {
+        let threads = 8;
+        let increments = 100;
+        let dir = tmpdir();
+        let seq = Arc::new(FileSeq::new(&dir, 1).unwrap());
+
+        let handles: Vec<_> = (0..threads)
+            .map(|_| {
+                let seq = Arc::clone(&seq);
+                thread::spawn(move || {
+                    (0..increments)
+                        .map(|_| seq.increment_and_get(1).unwrap())
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+
+        let mut values = HashSet::new();
+        for handle in handles {
+            for value in handle.join().unwrap() {
+                assert!(values.insert(value), "{} was handed out twice", value);
+            }
+        }
+
+        let total = threads * increments;
+        assert_eq!(values, (2..=total + 1).collect());
+        assert_eq!(total + 1, seq.value().unwrap());
+    }
+
+    #[test]
+    fn should_fail_to_try_lock_sequence_held_by_another_thread() {
+        let dir = tmpdir();
+        let seq = FileSeq::new(&dir, 1).unwrap().with_lock_mode(LockMode::Try);
+        let guard = seq.lock().unwrap();
+        let err = thread::scope(|s| s.spawn(|| seq.value().unwrap_err()).join().unwrap());
+        assert!(matches!(err, FileSeqError::Locked));
+        drop(guard);
+        assert_eq!(1, seq.value().unwrap());
+    }
+
+    #[test]
+    fn should_fall_back_to_backup_when_latest_is_empty() {
+        let dir = tmpdir();
+        let seq = FileSeq::new(&dir, 1).unwrap();
+        seq.increment_and_get(1).unwrap();
+        fs::write(seq.storage.files.version_path(2), []).unwrap();
+        assert_eq!(1, seq.value().unwrap());
+        assert!(fs::metadata(seq.storage.files.version_path(2)).is_err());
+        assert_eq!(2, seq.increment_and_get(1).unwrap());
+    }
+
+    #[test]
+    fn should_fall_back_to_backup_when_latest_is_truncated() {
+        let dir = tmpdir();
+        let seq = FileSeq::new(&dir, 1).unwrap();
+        seq.increment_and_get(1).unwrap();
+        fs::write(seq.storage.files.version_path(2), [0, 0, 0]).unwrap();
+        assert_eq!(1, seq.value().unwrap());
+    }
+
+    #[test]
+    fn should_ignore_truncated_backup() {
+        let dir = tmpdir();
+        let seq = FileSeq::new(&dir, 1).unwrap();
+        seq.increment_and_get(1).unwrap();
+        fs::write(seq.storage.files.version_path(1), [0, 0, 0]).unwrap();
+        assert_eq!(2, seq.value().unwrap());
+    }
+
+    #[test]
+    fn should_fail_when_both_files_are_truncated() {
+        let dir = tmpdir();
+        let seq = FileSeq::new(&dir, 1).unwrap();
+        seq.increment_and_get(1).unwrap();
+        fs::write(seq.storage.files.version_path(1), []).unwrap();
+        fs::write(seq.storage.files.version_path(2), [0, 0, 0]).unwrap();
+        assert!(matches!(seq.value(), Err(FileSeqError::Corrupted)));
+    }
+
+    #[test]
+    fn should_set_value_when_both_files_are_corrupted() {
+        let dir = tmpdir();
+        let seq = FileSeq::new(&dir, 1).unwrap();
+        seq.increment_and_get(1).unwrap();
+        fs::write(seq.storage.files.version_path(1), [0xff; 12]).unwrap();
+        fs::write(seq.storage.files.version_path(2), [0xff; 12]).unwrap();
+        seq.set(10).unwrap();
+        assert_eq!(10, seq.value().unwrap());
+
+        seq.delete().unwrap();
+        assert!(matches!(seq.set(10), Err(FileSeqError::NotFound)));
+    }
+
+    #[test]
+    fn should_keep_backup_when_setting_over_corrupted_latest() {
+        let dir = tmpdir();
+        let seq = FileSeq::builder(&dir)
+            .initial_value(1)
+            .repair_on_read(false)
+            .build()
+            .unwrap();
+        seq.increment_and_get(1).unwrap();
+        fs::write(seq.storage.files.version_path(2), [0xff; 12]).unwrap();
+        seq.set(10).unwrap();
+        assert_eq!(
+            FileState::Valid(1u64),
+            FileState::read(seq.storage.files.version_path(1))
+        );
+        assert_eq!(10, seq.value().unwrap());
+    }
+
+    #[test]
+    fn should_increment_with_every_durability() {
+        for durability in [Durability::None, Durability::File, Durability::FileAndDir] {
+            let dir = tmpdir();
+            let seq = FileSeq::new(&dir, 1).unwrap().with_durability(durability);
+            assert_eq!(2, seq.increment_and_get(1).unwrap());
+            assert_eq!(2, FileSeq::new(&dir, 1).unwrap().value().unwrap());
+        }
+    }
+
+    #[test]
+    fn should_not_leave_temporary_file_behind() {
+        let dir = tmpdir();
+        let seq = FileSeq::new(&dir, 1).unwrap();
+        seq.increment_and_get(1).unwrap();
+        assert!(fs::metadata(&seq.storage.files.tmp_path).is_err());
+    }
+
+    #[test]
+    fn should_ignore_partially_written_temporary_file() {
+        let dir = tmpdir();
+        let seq = FileSeq::new(&dir, 1).unwrap();
+        seq.increment_and_get(1).unwrap();
+        // Crashed while writing the next value
+        fs::write(&seq.storage.files.tmp_path, [0xff, 0xff]).unwrap();
+        assert_eq!(2, seq.value().unwrap());
+        assert_eq!(3, seq.increment_and_get(1).unwrap());
+        assert_eq!(3, seq.value().unwrap());
+    }
+
+    #[test]
+    fn should_use_backup_after_crash_between_renames() {
+        let dir = tmpdir();
+        let seq = FileSeq::new(&dir, 1).unwrap();
+        seq.increment_and_get(1).unwrap();
+        // Crashed after rotating the latest value, before renaming the new one
+        fs::write(&seq.storage.files.tmp_path, crate::record::encode(3u64)).unwrap();
+        fs::rename(
+            seq.storage.files.version_path(2),
+            seq.storage.files.version_path(1),
+        )
+        .unwrap();
+        assert_eq!(2, seq.value().unwrap());
+        assert_eq!(3, seq.increment_and_get(1).unwrap());
+        assert_eq!(
+            FileState::Valid(2u64),
+            FileState::read(seq.storage.files.version_path(1))
+        );
+    }
+
+    #[test]
+    fn should_delete_temporary_file() {
+        let dir = tmpdir();
+        let seq = FileSeq::new(&dir, 1).unwrap();
+        fs::write(&seq.storage.files.tmp_path, [0xff]).unwrap();
+        seq.delete().unwrap();
+        assert!(fs::metadata(&seq.storage.files.tmp_path).is_err());
+    }
+
+    #[test]
+    fn should_fall_back_to_backup_when_latest_fails_checksum() {
+        let dir = tmpdir();
+        let seq = FileSeq::new(&dir, 1).unwrap();
+        seq.increment_and_get(1).unwrap();
+        let mut bytes = fs::read(seq.storage.files.version_path(2)).unwrap();
+        // Turns the value into a huge number
+        bytes[6] ^= 0x80;
+        fs::write(seq.storage.files.version_path(2), bytes).unwrap();
+        assert_eq!(1, seq.value().unwrap());
+    }
+
+    #[test]
+    fn should_migrate_legacy_files() {
+        let dir = tmpdir();
+        fs::write(dir.join("_1.seq"), 4u64.to_be_bytes()).unwrap();
+        fs::write(dir.join("_2.seq"), 5u64.to_be_bytes()).unwrap();
+        let seq = FileSeq::new(&dir, 1).unwrap();
+        assert_eq!(5, seq.value().unwrap());
+        assert_eq!(6, seq.increment_and_get(1).unwrap());
+        assert_eq!(
+            crate::record::encode(6u64),
+            fs::read(seq.storage.files.version_path(2)).unwrap()
+        );
+    }
+
+    #[test]
+    fn should_migrate_legacy_values_with_newline_byte() {
+        let dir = tmpdir();
+        fs::write(dir.join("_1.seq"), 9u64.to_be_bytes()).unwrap();
+        fs::write(dir.join("_2.seq"), 10u64.to_be_bytes()).unwrap();
+        let seq = FileSeq::new(&dir, 1).unwrap();
+        assert_eq!(10, seq.value().unwrap());
+        assert_eq!(11, seq.increment_and_get(1).unwrap());
+    }
+
+    #[test]
+    fn should_report_deleted_sequence() {
+        let dir = tmpdir();
+        let seq = FileSeq::new(&dir, 1).unwrap();
+        seq.delete().unwrap();
+        assert!(matches!(seq.value(), Err(FileSeqError::NotFound)));
+        assert!(matches!(
+            seq.increment_and_get(1),
+            Err(FileSeqError::NotFound)
+        ));
+    }
+
+    #[test]
+    fn should_report_overflow() {
+        let dir = tmpdir();
+        let seq = FileSeq::new(&dir, u64::MAX - 1).unwrap();
+        assert_eq!(u64::MAX, seq.increment_and_get(1).unwrap());
+        assert!(matches!(
+            seq.increment_and_get(1),
+            Err(FileSeqError::Overflow)
+        ));
+        assert_eq!(u64::MAX, seq.value().unwrap());
+    }
+
+    #[test]
+    fn should_apply_overflow_policy() {
+        let dir = tmpdir();
+        let seq = FileSeq::new(&dir, u64::MAX - 1)
+            .unwrap()
+            .with_overflow_policy(OverflowPolicy::Wrap)
+            .unwrap();
+        assert_eq!(u64::MAX - 1, seq.get_and_increment(2).unwrap());
+        assert_eq!(0, seq.value().unwrap());
+
+        let seq = seq.with_overflow_policy(OverflowPolicy::Saturate).unwrap();
+        seq.increment_and_get(u64::MAX).unwrap();
+        assert_eq!(u64::MAX, seq.increment_and_get(1).unwrap());
+    }
+
+    #[test]
+    fn should_reject_overflow_policy_out_of_bounds() {
+        let dir = tmpdir();
+        let seq = FileSeq::builder(&dir).bounds(1, 10).build().unwrap();
+        let result = seq.with_overflow_policy(OverflowPolicy::WrapTo(500));
+        assert!(matches!(result, Err(FileSeqError::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn should_decrement() {
+        let dir = tmpdir();
+        let seq = FileSeq::new(&dir, 5).unwrap();
+        assert_eq!(5, seq.get_and_decrement(2).unwrap());
+        assert_eq!(2, seq.decrement_and_get(1).unwrap());
+        assert_eq!(2, seq.value().unwrap());
+        assert!(matches!(
+            seq.decrement_and_get(3),
+            Err(FileSeqError::Overflow)
+        ));
+        assert_eq!(2, seq.value().unwrap());
+    }
+
+    #[test]
+    fn should_set_lower_value() {
+        let dir = tmpdir();
+        let seq = FileSeq::new(&dir, 5).unwrap();
+        seq.set(1).unwrap();
+        assert_eq!(1, seq.value().unwrap());
+        assert_eq!(
+            FileState::Valid(5u64),
+            FileState::read(seq.storage.files.version_path(1))
+        );
+        assert_eq!(2, seq.increment_and_get(1).unwrap());
+    }
+
+    #[test]
+    fn should_compare_and_set_across_threads() {
+        let dir = tmpdir();
+        let seq = Arc::new(FileSeq::new(&dir, 0).unwrap());
+        let threads = 8;
+        let increments = 25;
+
+        let handles: Vec<_> = (0..threads)
+            .map(|_| {
+                let seq = Arc::clone(&seq);
+                thread::spawn(move || {
+                    let mut done = 0;
+                    while done < increments {
+                        let value = seq.value().unwrap();
+                        if seq.compare_and_set(value, value + 1).unwrap() {
+                            done += 1;
+                        }
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(threads * increments, seq.value().unwrap());
+    }
+
+    #[test]
+    fn should_reserve_block() {
+        let dir = tmpdir();
+        let seq = FileSeq::new(&dir, 1).unwrap();
+        assert_eq!(1..11, seq.reserve(10).unwrap());
+        assert_eq!(11..12, seq.reserve(1).unwrap());
+        assert_eq!(12, seq.value().unwrap());
+    }
+
+    #[test]
+    fn should_fail_to_reserve_past_overflow() {
+        let dir = tmpdir();
+        let seq = FileSeq::new(&dir, u64::MAX - 5).unwrap();
+        assert!(matches!(seq.reserve(10), Err(FileSeqError::Overflow)));
+        assert_eq!(u64::MAX - 5, seq.value().unwrap());
+    }
+
+    #[test]
+    fn should_store_other_value_types() {
+        let seq = FileSeq::new_typed(tmpdir(), u32::MAX - 1).unwrap();
+        assert_eq!(u32::MAX, seq.increment_and_get(1).unwrap());
+        assert!(matches!(
+            seq.increment_and_get(1),
+            Err(FileSeqError::Overflow)
+        ));
+
+        let seq = FileSeq::new_typed(tmpdir(), u128::from(u64::MAX)).unwrap();
+        assert_eq!(u128::from(u64::MAX) + 1, seq.increment_and_get(1).unwrap());
+
+        let seq = FileSeq::new_typed(tmpdir(), 1i64).unwrap();
+        assert_eq!(-1, seq.decrement_and_get(2).unwrap());
+        assert_eq!(-1, seq.value().unwrap());
+        assert_eq!(-1..9, seq.reserve(10).unwrap());
+    }
+
+    #[test]
+    fn should_reject_negative_increments() {
+        let seq = FileSeq::new_typed(tmpdir(), 10i64).unwrap();
+        assert!(matches!(seq.reserve(-5), Err(FileSeqError::Overflow)));
+        let result = seq.increment_and_get(-1);
+        assert!(matches!(result, Err(FileSeqError::Overflow)));
+        let result = seq.decrement_and_get(-1);
+        assert!(matches!(result, Err(FileSeqError::Overflow)));
+        assert_eq!(10, seq.value().unwrap());
+    }
+
+    #[test]
+    fn should_not_read_value_of_other_type() {
+        let dir = tmpdir();
+        FileSeq::new_typed(&dir, 1u32).unwrap();
+        let seq = FileSeq::new(&dir, 1).unwrap();
+        assert!(matches!(seq.value(), Err(FileSeqError::Corrupted)));
+    }
+
+    #[test]
+    fn should_read_files_of_both_codecs() {
+        let dir = tmpdir();
+        let seq = FileSeq::new(&dir, 1).unwrap();
+        let seq = seq.with_codec(Codec::Text);
+        assert_eq!(2, seq.increment_and_get(1).unwrap());
+        assert_eq!(
+            FileState::Valid(1u64),
+            FileState::read(seq.storage.files.version_path(1))
+        );
+        assert_eq!(
+            "2\ncrc32 1ad5be0d\n",
+            fs::read_to_string(seq.storage.files.version_path(2)).unwrap()
+        );
+
+        let seq = FileSeq::new(&dir, 1).unwrap();
+        assert_eq!(3, seq.increment_and_get(1).unwrap());
+        assert_eq!(
+            FileState::Valid(2u64),
+            FileState::read(seq.storage.files.version_path(1))
+        );
+    }
+
+    #[test]
+    fn should_fall_back_to_backup_when_text_fails_checksum() {
+        let dir = tmpdir();
+        let seq = FileSeq::new(&dir, 1).unwrap().with_codec(Codec::Text);
+        seq.increment_and_get(1).unwrap();
+        fs::write(seq.storage.files.version_path(2), "20\ncrc32 1ad5be0d\n").unwrap();
+        assert_eq!(1, seq.value().unwrap());
+    }
+
+    #[test]
+    fn should_convert_files() {
+        let dir = tmpdir();
+        let mut seq = FileSeq::new(&dir, 1).unwrap();
+        seq.increment_and_get(1).unwrap();
+        seq.convert(Codec::Text).unwrap();
+        assert!(fs::read_to_string(seq.storage.files.version_path(1))
+            .unwrap()
+            .starts_with("1\n"));
+        assert!(fs::read_to_string(seq.storage.files.version_path(2))
+            .unwrap()
+            .starts_with("2\n"));
+        assert_eq!(3, seq.increment_and_get(1).unwrap());
+        assert!(fs::read_to_string(seq.storage.files.version_path(2))
+            .unwrap()
+            .starts_with("3\n"));
+
+        seq.convert(Codec::Binary).unwrap();
+        assert_eq!(
+            crate::record::encode(3u64),
+            fs::read(seq.storage.files.version_path(2)).unwrap()
+        );
+        assert!(fs::metadata(&seq.storage.files.tmp_path).is_err());
+    }
+
+    #[test]
+    fn should_not_convert_corrupted_file() {
+        let dir = tmpdir();
+        let mut seq = FileSeq::new(&dir, 1).unwrap();
+        seq.increment_and_get(1).unwrap();
+        fs::write(seq.storage.files.version_path(2), [0xff; 3]).unwrap();
+        seq.convert(Codec::Text).unwrap();
+        assert_eq!(
+            vec![0xff; 3],
+            fs::read(seq.storage.files.version_path(2)).unwrap()
+        );
+        assert_eq!(1, seq.value().unwrap());
+    }
 }
@@ -0,0 +1,202 @@
+use std::fs::{File, OpenOptions, TryLockError};
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard, TryLockError as MutexTryLockError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::error::{FileSeqError, Result};
+use crate::files;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// How a sequence waits for its locks.
+///
+/// Threads of the same process are serialized by a mutex, other processes by
+/// an advisory `flock`-style lock on a file in the store directory, so it
+/// only excludes processes that also go through `FileSeq`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LockMode {
+    /// Waits until the lock is available.
+    #[default]
+    Blocking,
+    /// Fails with [`FileSeqError::Locked`] if the lock is held by someone else.
+    Try,
+    /// Waits up to the given duration, then fails with [`FileSeqError::LockTimeout`].
+    Timeout(Duration),
+}
+
+/// Holds the lock file locked until dropped.
+#[derive(Debug)]
+pub(crate) struct LockGuard {
+    file: File,
+}
+
+impl Drop for LockGuard {
+    fn drop(&mut self) {
+        // Closing the file would release the lock anyway
+        let _ = self.file.unlock();
+    }
+}
+
+/// Holds both the in-process mutex and the lock file until dropped.
+#[derive(Debug)]
+pub(crate) struct SeqGuard<'a> {
+    _file: Option<LockGuard>,
+    _mutex: MutexGuard<'a, ()>,
+}
+
+/// Locks `mutex`, then the lock file at `path` if there is one.
+pub(crate) fn acquire_all<'a>(
+    mutex: &'a Mutex<()>,
+    path: Option<&Path>,
+    mode: LockMode,
+    file_mode: Option<u32>,
+) -> Result<SeqGuard<'a>> {
+    let started = Instant::now();
+    let mutex = lock_mutex(mutex, mode)?;
+    // The time spent on the mutex counts towards the timeout
+    let mode = match mode {
+        LockMode::Timeout(timeout) => {
+            LockMode::Timeout(timeout.checked_sub(started.elapsed()).unwrap_or_default())
+        }
+        mode => mode,
+    };
+    let file = match path {
+        Some(path) => Some(acquire(path, mode, file_mode)?),
+        None => None,
+    };
+    Ok(SeqGuard {
+        _file: file,
+        _mutex: mutex,
+    })
+}
+
+fn lock_mutex(mutex: &Mutex<()>, mode: LockMode) -> Result<MutexGuard<'_, ()>> {
+    // The mutex guards no data, so a panic while holding it leaves nothing
+    // inconsistent behind.
+    match mode {
+        LockMode::Blocking => Ok(mutex.lock().unwrap_or_else(|e| e.into_inner())),
+        LockMode::Try => match mutex.try_lock() {
+            Ok(guard) => Ok(guard),
+            Err(MutexTryLockError::Poisoned(e)) => Ok(e.into_inner()),
+            Err(MutexTryLockError::WouldBlock) => Err(FileSeqError::Locked),
+        },
+        LockMode::Timeout(timeout) => {
+            let deadline = Instant::now() + timeout;
+            loop {
+                match mutex.try_lock() {
+                    Ok(guard) => return Ok(guard),
+                    Err(MutexTryLockError::Poisoned(e)) => return Ok(e.into_inner()),
+                    Err(MutexTryLockError::WouldBlock) => {
+                        let now = Instant::now();
+                        if now >= deadline {
+                            return Err(FileSeqError::LockTimeout);
+                        }
+                        thread::sleep(POLL_INTERVAL.min(deadline - now));
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn lock_file_options(file_mode: Option<u32>) -> OpenOptions {
+    let mut options = OpenOptions::new();
+    options.read(true).write(true).create(true).truncate(false);
+    files::set_file_mode(&mut options, file_mode);
+    options
+}
+
+fn acquire<P: AsRef<Path>>(path: P, mode: LockMode, file_mode: Option<u32>) -> Result<LockGuard> {
+    let file = lock_file_options(file_mode).open(path.as_ref())?;
+
+    match mode {
+        LockMode::Blocking => file.lock()?,
+        LockMode::Try => match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => return Err(FileSeqError::Locked),
+            Err(TryLockError::Error(e)) => return Err(e.into()),
+        },
+        LockMode::Timeout(timeout) => {
+            let deadline = Instant::now() + timeout;
+            loop {
+                match file.try_lock() {
+                    Ok(()) => break,
+                    Err(TryLockError::WouldBlock) => {
+                        let now = Instant::now();
+                        if now >= deadline {
+                            return Err(FileSeqError::LockTimeout);
+                        }
+                        thread::sleep(POLL_INTERVAL.min(deadline - now));
+                    }
+                    Err(TryLockError::Error(e)) => return Err(e.into()),
+                }
+            }
+        }
+    }
+
+    Ok(LockGuard { file })
+}
+
+/// Holds both the async mutex and the lock file until dropped.
+#[cfg(feature = "tokio")]
+#[derive(Debug)]
+pub(crate) struct AsyncSeqGuard<'a> {
+    _file: LockGuard,
+    _mutex: tokio::sync::MutexGuard<'a, ()>,
+}
+
+/// Same as [`acquire_all`], but waits without blocking the executor.
+///
+/// The lock file is polled, as there's no way to be woken up once another
+/// process releases it.
+#[cfg(feature = "tokio")]
+pub(crate) async fn acquire_all_async<'a, P: AsRef<Path>>(
+    mutex: &'a tokio::sync::Mutex<()>,
+    path: P,
+    mode: LockMode,
+) -> Result<AsyncSeqGuard<'a>> {
+    let deadline = match mode {
+        LockMode::Timeout(timeout) => Some(Instant::now() + timeout),
+        _ => None,
+    };
+    let mutex = match mode {
+        LockMode::Blocking => mutex.lock().await,
+        LockMode::Try => mutex.try_lock().map_err(|_| FileSeqError::Locked)?,
+        LockMode::Timeout(timeout) => tokio::time::timeout(timeout, mutex.lock())
+            .await
+            .map_err(|_| FileSeqError::LockTimeout)?,
+    };
+
+    let file = tokio::fs::OpenOptions::from(lock_file_options(None))
+        .open(path.as_ref())
+        .await?
+        .into_std()
+        .await;
+    loop {
+        match file.try_lock() {
+            Ok(()) => break,
+            Err(TryLockError::WouldBlock) => {
+                let mut wait = POLL_INTERVAL;
+                match (mode, deadline) {
+                    (LockMode::Try, _) => return Err(FileSeqError::Locked),
+                    (_, Some(deadline)) => {
+                        let now = Instant::now();
+                        if now >= deadline {
+                            return Err(FileSeqError::LockTimeout);
+                        }
+                        wait = wait.min(deadline - now);
+                    }
+                    _ => {}
+                }
+                tokio::time::sleep(wait).await;
+            }
+            Err(TryLockError::Error(e)) => return Err(e.into()),
+        }
+    }
+
+    Ok(AsyncSeqGuard {
+        _file: LockGuard { file },
+        _mutex: mutex,
+    })
+}
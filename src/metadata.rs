@@ -0,0 +1,442 @@
+//! Settings stored next to a sequence, so every process agrees on them.
+//!
+//! The metadata file holds one `key = value` pair per line:
+//!
+//! ```text
+//! version = 1
+//! initial = 1
+//! step = 1
+//! min = 1
+//! max = 999999
+//! overflow = wrap
+//! versions = 2
+//! layout = files
+//! created = 1767225600
+//! owner = billing
+//! ```
+
+use std::env;
+use std::fmt::Write;
+use std::io::{self, ErrorKind};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::durability::Durability;
+use crate::error::{FileSeqError, Result};
+use crate::overflow::{Bounds, OverflowPolicy};
+use crate::storage::{Layout, SeqStorage, Slot};
+use crate::value::SeqValue;
+
+const VERSION: u32 = 1;
+/// Versions kept by sequences created before their number was stored.
+pub(crate) const DEFAULT_VERSIONS: u32 = 2;
+
+/// What is recorded about a sequence when it's created.
+///
+/// Opening the sequence with a step, bounds, overflow policy or number of
+/// versions other than the recorded ones fails with
+/// [`FileSeqError::InvalidConfig`].
+///
+/// # Example
+///
+/// ```
+/// use file_seq::FileSeq;
+/// use std::path::Path;
+///
+/// let dir = Path::new("/tmp/example_metadata");
+/// # let _ = std::fs::remove_dir_all(&dir);
+///
+/// FileSeq::builder(&dir).initial_value(1).owner("billing").build().unwrap();
+///
+/// let seq = FileSeq::open(&dir).unwrap();
+/// assert_eq!(Some(1), seq.metadata().initial_value);
+/// assert_eq!("billing", seq.metadata().owner);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Metadata<T> {
+    /// Version of the metadata format.
+    pub format_version: u32,
+    /// The value the sequence was created with, `None` for sequences created
+    /// before metadata was recorded.
+    pub initial_value: Option<T>,
+    /// The increment of [`FileSeq::next`](crate::FileSeq::next).
+    pub step: T,
+    /// The lowest value of the sequence.
+    pub min: T,
+    /// The highest value of the sequence.
+    pub max: T,
+    pub overflow_policy: OverflowPolicy<T>,
+    /// How many versions of the value are kept, see
+    /// [`FileSeqBuilder::versions`](crate::FileSeqBuilder::versions).
+    pub versions: u32,
+    /// How the versions are laid out, [`Layout::Files`] for sequences
+    /// created before it was recorded.
+    pub layout: Layout,
+    /// When the metadata was recorded, to the second.
+    pub created: SystemTime,
+    /// Who created the sequence, by default the user running the process.
+    pub owner: String,
+}
+
+/// The settings a process opens a sequence with, `None` for the ones it
+/// didn't set.
+#[derive(Debug, Clone)]
+pub(crate) struct Requested<T> {
+    pub(crate) step: Option<T>,
+    pub(crate) bounds: Option<Bounds<T>>,
+    pub(crate) overflow_policy: Option<OverflowPolicy<T>>,
+    pub(crate) versions: Option<u32>,
+    pub(crate) owner: Option<String>,
+}
+
+impl<T> Default for Requested<T> {
+    fn default() -> Self {
+        Self {
+            step: None,
+            bounds: None,
+            overflow_policy: None,
+            versions: None,
+            owner: None,
+        }
+    }
+}
+
+impl<T: SeqValue> Requested<T> {
+    pub(crate) fn step(&self) -> T {
+        self.step.unwrap_or(T::ONE)
+    }
+
+    pub(crate) fn bounds(&self) -> Bounds<T> {
+        self.bounds.unwrap_or_else(Bounds::full)
+    }
+
+    pub(crate) fn overflow_policy(&self) -> OverflowPolicy<T> {
+        self.overflow_policy.unwrap_or_default()
+    }
+
+    pub(crate) fn versions(&self) -> u32 {
+        self.versions.unwrap_or(DEFAULT_VERSIONS)
+    }
+
+    /// Returns the metadata of a sequence created now with `initial_value`.
+    pub(crate) fn metadata(&self, initial_value: Option<T>) -> Metadata<T> {
+        let bounds = self.bounds();
+        let created = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        Metadata {
+            format_version: VERSION,
+            initial_value,
+            step: self.step(),
+            min: bounds.min,
+            max: bounds.max,
+            overflow_policy: self.overflow_policy(),
+            versions: self.versions(),
+            layout: Layout::default(),
+            created: UNIX_EPOCH + Duration::from_secs(created.as_secs()),
+            owner: self.owner.clone().unwrap_or_else(current_user),
+        }
+    }
+
+    /// Fails if fewer versions were requested than `found` for a sequence
+    /// without metadata.
+    pub(crate) fn check_found_versions(&self, found: u32) -> Result<()> {
+        if self.versions.is_some_and(|versions| versions < found) {
+            return Err(conflict("the sequence keeps more versions"));
+        }
+        Ok(())
+    }
+
+    /// Fails if a setting differs from the stored one.
+    pub(crate) fn check(&self, stored: &Metadata<T>) -> Result<()> {
+        if self.step.is_some_and(|step| step != stored.step) {
+            return Err(conflict("the sequence was created with another step"));
+        }
+        if self.bounds.is_some_and(|bounds| bounds != stored.bounds()) {
+            return Err(conflict("the sequence was created with other bounds"));
+        }
+        if self
+            .overflow_policy
+            .is_some_and(|policy| policy != stored.overflow_policy)
+        {
+            return Err(conflict(
+                "the sequence was created with another overflow policy",
+            ));
+        }
+        if self
+            .versions
+            .is_some_and(|versions| versions != stored.versions)
+        {
+            return Err(conflict(
+                "the sequence was created with another number of versions",
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Returns how many versions a sequence without metadata keeps, judging by
+/// the versions found in `storage`, so one that lost its metadata isn't read
+/// from an older version.
+pub(crate) fn found_versions<S: SeqStorage>(storage: &S) -> u32 {
+    let mut versions = DEFAULT_VERSIONS;
+    let mut n = 1;
+    // A crash during a rotation leaves at most one version missing in between
+    while n <= versions + 2 {
+        if storage.exists(Slot::Version(n)) {
+            versions = versions.max(n);
+        }
+        n += 1;
+    }
+    versions
+}
+
+fn conflict(msg: &str) -> FileSeqError {
+    FileSeqError::InvalidConfig(msg.to_string())
+}
+
+fn current_user() -> String {
+    env::var("USER")
+        .or_else(|_| env::var("USERNAME"))
+        .unwrap_or_default()
+}
+
+impl<T: SeqValue> Metadata<T> {
+    pub(crate) fn bounds(&self) -> Bounds<T> {
+        Bounds {
+            min: self.min,
+            max: self.max,
+        }
+    }
+
+    /// Returns the stored metadata, `None` for sequences created without.
+    pub(crate) fn read<S: SeqStorage>(storage: &S) -> Result<Option<Self>> {
+        Self::from_contents(storage.read(Slot::Metadata))
+    }
+
+    /// Interprets the result of reading the metadata file.
+    pub(crate) fn from_contents(contents: io::Result<Vec<u8>>) -> Result<Option<Self>> {
+        match contents {
+            Ok(bytes) => String::from_utf8(bytes)
+                .ok()
+                .and_then(|text| Self::decode(&text))
+                .map(Some)
+                .ok_or(FileSeqError::Corrupted),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Fails if the sequence is stored in another layout than `layout`.
+    pub(crate) fn check_layout(&self, layout: Layout) -> Result<()> {
+        if self.layout != layout {
+            return Err(conflict(&format!(
+                "the sequence is stored in the {} layout",
+                self.layout
+            )));
+        }
+        Ok(())
+    }
+
+    /// Stores the metadata, renamed into place so a crash leaves either the
+    /// old or the new metadata behind.
+    pub(crate) fn write<S: SeqStorage>(&self, storage: &S, durability: Durability) -> Result<()> {
+        let text = self.encode();
+        storage.write(Slot::PendingMetadata, text.as_bytes(), durability)?;
+        storage.rename(Slot::PendingMetadata, Slot::Metadata)?;
+        Ok(storage.sync(durability)?)
+    }
+
+    pub(crate) fn encode(&self) -> String {
+        let mut text = String::new();
+        writeln!(text, "version = {}", self.format_version).unwrap();
+        if let Some(initial_value) = self.initial_value {
+            writeln!(text, "initial = {}", initial_value).unwrap();
+        }
+        writeln!(text, "step = {}", self.step).unwrap();
+        writeln!(text, "min = {}", self.min).unwrap();
+        writeln!(text, "max = {}", self.max).unwrap();
+        let overflow = match self.overflow_policy {
+            OverflowPolicy::Error => "error".to_string(),
+            OverflowPolicy::Saturate => "saturate".to_string(),
+            OverflowPolicy::Wrap => "wrap".to_string(),
+            OverflowPolicy::WrapTo(value) => format!("wrap_to {}", value),
+        };
+        writeln!(text, "overflow = {}", overflow).unwrap();
+        writeln!(text, "versions = {}", self.versions).unwrap();
+        writeln!(text, "layout = {}", self.layout).unwrap();
+        let created = self.created.duration_since(UNIX_EPOCH).unwrap_or_default();
+        writeln!(text, "created = {}", created.as_secs()).unwrap();
+        // Line breaks would end the value early
+        writeln!(text, "owner = {}", self.owner.replace(['\r', '\n'], " ")).unwrap();
+        text
+    }
+
+    fn decode(text: &str) -> Option<Self> {
+        let (mut version, mut initial_value, mut step) = (None, None, None);
+        let (mut min, mut max, mut overflow) = (None, None, None);
+        let (mut versions, mut layout, mut created, mut owner) = (None, None, None, None);
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line.split_once('=')?;
+            let value = value.trim();
+            match key.trim() {
+                "version" => version = Some(value.parse::<u32>().ok()?),
+                "initial" => initial_value = Some(value.parse().ok()?),
+                "step" => step = Some(value.parse().ok()?),
+                "min" => min = Some(value.parse().ok()?),
+                "max" => max = Some(value.parse().ok()?),
+                "overflow" => overflow = Some(parse_overflow_policy(value)?),
+                "versions" => versions = Some(value.parse::<u32>().ok()?),
+                "layout" => layout = Some(parse_layout(value)?),
+                "created" => created = Some(value.parse::<u64>().ok()?),
+                "owner" => owner = Some(value.to_string()),
+                // Written by a newer version, which bumps the version if the
+                // key matters to older ones
+                _ => {}
+            }
+        }
+        if version? != VERSION {
+            return None;
+        }
+        Some(Self {
+            format_version: VERSION,
+            initial_value,
+            step: step?,
+            min: min?,
+            max: max?,
+            overflow_policy: overflow?,
+            versions: versions.filter(|&versions| versions >= 2)?,
+            layout: layout.unwrap_or_default(),
+            created: UNIX_EPOCH + Duration::from_secs(created?),
+            owner: owner?,
+        })
+    }
+}
+
+fn parse_layout(value: &str) -> Option<Layout> {
+    match value {
+        "files" => Some(Layout::Files),
+        "ring" => Some(Layout::Ring),
+        _ => None,
+    }
+}
+
+fn parse_overflow_policy<T: SeqValue>(value: &str) -> Option<OverflowPolicy<T>> {
+    match value {
+        "error" => Some(OverflowPolicy::Error),
+        "saturate" => Some(OverflowPolicy::Saturate),
+        "wrap" => Some(OverflowPolicy::Wrap),
+        _ => {
+            let value = value.strip_prefix("wrap_to")?.trim();
+            Some(OverflowPolicy::WrapTo(value.parse().ok()?))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::metadata::{Metadata, Requested};
+    use crate::overflow::{Bounds, OverflowPolicy};
+    use crate::storage::Layout;
+    use crate::FileSeqError;
+
+    fn metadata(min: i64, max: i64, overflow_policy: OverflowPolicy<i64>) -> Metadata<i64> {
+        Requested {
+            bounds: Some(Bounds { min, max }),
+            overflow_policy: Some(overflow_policy),
+            owner: Some("billing".to_string()),
+            ..Requested::default()
+        }
+        .metadata(Some(min))
+    }
+
+    #[test]
+    fn should_round_trip() {
+        for overflow_policy in [
+            OverflowPolicy::Error,
+            OverflowPolicy::Saturate,
+            OverflowPolicy::Wrap,
+            OverflowPolicy::WrapTo(-5),
+        ] {
+            let metadata = metadata(-10, 10, overflow_policy);
+            assert_eq!(Some(metadata.clone()), Metadata::decode(&metadata.encode()));
+        }
+
+        let metadata = Metadata {
+            initial_value: None,
+            owner: String::new(),
+            layout: Layout::Ring,
+            ..metadata(-10, 10, OverflowPolicy::Error)
+        };
+        assert_eq!(Some(metadata.clone()), Metadata::decode(&metadata.encode()));
+    }
+
+    #[test]
+    fn should_default_to_files_layout() {
+        let text = metadata(1, 10, OverflowPolicy::Wrap).encode();
+        let text = text.replace("layout = files\n", "");
+        let metadata = Metadata::<i64>::decode(&text).unwrap();
+        assert_eq!(Layout::Files, metadata.layout);
+        let result = metadata.check_layout(Layout::Ring);
+        assert!(matches!(result, Err(FileSeqError::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn should_reject_invalid_metadata() {
+        let text = metadata(1, 10, OverflowPolicy::Wrap).encode();
+        for invalid in [
+            text.replace("version = 1", "version = 2"),
+            text.replace("min = 1\n", ""),
+            text.replace("step = 1\n", ""),
+            text.replace("versions = 2", "versions = 1"),
+            text.replace("owner = billing\n", ""),
+            text.replace("wrap", "bounce"),
+            text.replace("layout = files", "layout = tape"),
+            text.replace("max = 10", "max = ten"),
+            text.replace("max = 10", "max 10"),
+            text.replace("created = ", "created = -"),
+        ] {
+            assert_eq!(None, Metadata::<i64>::decode(&invalid), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn should_check_requested_settings() {
+        let stored = metadata(1, 10, OverflowPolicy::Wrap);
+        assert!(Requested::default().check(&stored).is_ok());
+        let requested = Requested {
+            step: Some(1),
+            bounds: Some(stored.bounds()),
+            overflow_policy: Some(OverflowPolicy::Wrap),
+            versions: Some(2),
+            owner: Some("someone else".to_string()),
+        };
+        assert!(requested.check(&stored).is_ok());
+        for requested in [
+            Requested {
+                step: Some(2),
+                ..Requested::default()
+            },
+            Requested {
+                bounds: Some(Bounds { min: 1, max: 11 }),
+                ..Requested::default()
+            },
+            Requested {
+                overflow_policy: Some(OverflowPolicy::Error),
+                ..Requested::default()
+            },
+            Requested {
+                versions: Some(3),
+                ..Requested::default()
+            },
+        ] {
+            assert!(matches!(
+                requested.check(&stored),
+                Err(FileSeqError::InvalidConfig(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,333 @@
+use std::ops::Range;
+
+use crate::error::{FileSeqError, Result};
+use crate::value::SeqValue;
+
+/// What an increment does when the result doesn't fit in the sequence.
+///
+/// The sequence ends at the largest value of its type, or at the maximum set
+/// with [`FileSeqBuilder::bounds`](crate::FileSeqBuilder::bounds).
+/// Decrements below the smallest value are handled the same way, wrapping
+/// policies continue from the largest value and `Saturate` stops at the
+/// smallest one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy<T = u64> {
+    /// Fails with [`FileSeqError::Overflow`] and leaves the sequence unchanged.
+    #[default]
+    Error,
+    /// Stops at the largest value. Further increments keep returning it.
+    Saturate,
+    /// Continues from the smallest value.
+    Wrap,
+    /// Continues from the given value.
+    WrapTo(T),
+}
+
+/// The values a sequence may hold, both ends inclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Bounds<T> {
+    pub(crate) min: T,
+    pub(crate) max: T,
+}
+
+impl<T: SeqValue> Bounds<T> {
+    pub(crate) fn full() -> Self {
+        Self {
+            min: T::MIN,
+            max: T::MAX,
+        }
+    }
+
+    pub(crate) fn contains(&self, value: T) -> bool {
+        self.min <= value && value <= self.max
+    }
+
+    /// Returns `value` if it's within the bounds.
+    pub(crate) fn check(&self, value: T) -> Result<T> {
+        if self.contains(value) {
+            Ok(value)
+        } else {
+            Err(FileSeqError::OutOfBounds)
+        }
+    }
+}
+
+impl<T: SeqValue> OverflowPolicy<T> {
+    /// Returns the value the sequence continues from after an overflow.
+    fn wrap_value(self, bounds: Bounds<T>) -> Option<T> {
+        match self {
+            OverflowPolicy::Error | OverflowPolicy::Saturate => None,
+            OverflowPolicy::Wrap => Some(bounds.min),
+            OverflowPolicy::WrapTo(min) => Some(min),
+        }
+    }
+
+    /// Returns `value` plus `increment`, which can't be negative.
+    /// Returns the policy if the value it continues from is within `bounds`.
+    pub(crate) fn check(self, bounds: Bounds<T>) -> Result<Self> {
+        match self.wrap_value(bounds) {
+            Some(value) if !bounds.contains(value) => Err(FileSeqError::InvalidConfig(
+                "wrap value is out of bounds".to_string(),
+            )),
+            _ => Ok(self),
+        }
+    }
+
+    pub(crate) fn add(self, value: T, increment: T, bounds: Bounds<T>) -> Result<T> {
+        if increment < T::ZERO {
+            return Err(FileSeqError::Overflow);
+        }
+        let max = bounds.max;
+        match value.checked_add(increment) {
+            Some(next) if next <= max => return Ok(next),
+            _ => {}
+        }
+        if value > max {
+            return Err(FileSeqError::Overflow);
+        }
+        match self {
+            OverflowPolicy::Error => Err(FileSeqError::Overflow),
+            OverflowPolicy::Saturate => Ok(max),
+            OverflowPolicy::Wrap | OverflowPolicy::WrapTo(_) => {
+                let start = self.wrap_value(bounds).unwrap();
+                // How far past max the increment goes, counting start as the
+                // first value after it.
+                let excess = max
+                    .checked_sub(value)
+                    .and_then(|headroom| increment.checked_sub(headroom))
+                    .and_then(|excess| excess.checked_sub(T::ONE))
+                    .ok_or(FileSeqError::Overflow)?;
+                start
+                    .checked_add(wrap_excess(excess, start, max))
+                    .ok_or(FileSeqError::Overflow)
+            }
+        }
+    }
+
+    /// Returns the block of `len` values starting at `value`.
+    ///
+    /// A block is never split around an overflow, wrapping policies start it
+    /// over at the wrap value and `Saturate` shortens it to end at the
+    /// largest value. `len` can't be negative.
+    pub(crate) fn block(self, value: T, len: T, bounds: Bounds<T>) -> Result<Range<T>> {
+        if len < T::ZERO {
+            return Err(FileSeqError::Overflow);
+        }
+        let max = bounds.max;
+        match value.checked_add(len) {
+            Some(end) if end <= max => return Ok(value..end),
+            _ => {}
+        }
+        let start = match self {
+            OverflowPolicy::Error => return Err(FileSeqError::Overflow),
+            OverflowPolicy::Saturate => return Ok(value.min(max)..max),
+            OverflowPolicy::Wrap | OverflowPolicy::WrapTo(_) => self.wrap_value(bounds).unwrap(),
+        };
+        match start.checked_add(len) {
+            Some(end) if end <= max => Ok(start..end),
+            _ => Err(FileSeqError::Overflow),
+        }
+    }
+
+    /// Returns `value` minus `decrement`, which can't be negative.
+    pub(crate) fn sub(self, value: T, decrement: T, bounds: Bounds<T>) -> Result<T> {
+        if decrement < T::ZERO {
+            return Err(FileSeqError::Overflow);
+        }
+        let (min, max) = (bounds.min, bounds.max);
+        match value.checked_sub(decrement) {
+            Some(next) if next >= min => return Ok(next),
+            _ => {}
+        }
+        if value < min {
+            return Err(FileSeqError::Overflow);
+        }
+        match self {
+            OverflowPolicy::Error => Err(FileSeqError::Overflow),
+            OverflowPolicy::Saturate => Ok(min),
+            OverflowPolicy::Wrap | OverflowPolicy::WrapTo(_) => {
+                let start = self.wrap_value(bounds).unwrap();
+                // How far below min the decrement goes, counting max as the
+                // first value after it.
+                let excess = value
+                    .checked_sub(min)
+                    .and_then(|headroom| decrement.checked_sub(headroom))
+                    .and_then(|excess| excess.checked_sub(T::ONE))
+                    .ok_or(FileSeqError::Overflow)?;
+                max.checked_sub(wrap_excess(excess, start, max))
+                    .ok_or(FileSeqError::Overflow)
+            }
+        }
+    }
+}
+
+/// Reduces `excess` to the range between `start` and `max`, for increments
+/// that go around it more than once.
+fn wrap_excess<T: SeqValue>(excess: T, start: T, max: T) -> T {
+    let range = max
+        .checked_sub(start)
+        .and_then(|range| range.checked_add(T::ONE));
+    match range {
+        Some(range) => excess.checked_rem(range).unwrap_or(excess),
+        // The range is larger than any increment
+        None => excess,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::overflow::Bounds;
+    use crate::{FileSeqError, OverflowPolicy};
+
+    fn full<T: crate::SeqValue>() -> Bounds<T> {
+        Bounds::full()
+    }
+
+    #[test]
+    fn should_add_without_overflow() {
+        for policy in [
+            OverflowPolicy::Error,
+            OverflowPolicy::Saturate,
+            OverflowPolicy::Wrap,
+            OverflowPolicy::WrapTo(10),
+        ] {
+            assert_eq!(3, policy.add(1, 2, full()).unwrap());
+            assert_eq!(u64::MAX, policy.add(u64::MAX - 1, 1, full()).unwrap());
+        }
+    }
+
+    #[test]
+    fn should_fail_on_overflow() {
+        let result = OverflowPolicy::Error.add(u64::MAX, 1, full());
+        assert!(matches!(result, Err(FileSeqError::Overflow)));
+    }
+
+    #[test]
+    fn should_saturate_on_overflow() {
+        assert_eq!(
+            u64::MAX,
+            OverflowPolicy::Saturate
+                .add(u64::MAX - 1, 5, full())
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn should_wrap_on_overflow() {
+        assert_eq!(0, OverflowPolicy::Wrap.add(u64::MAX, 1, full()).unwrap());
+        assert_eq!(
+            3,
+            OverflowPolicy::Wrap.add(u64::MAX - 1, 5, full()).unwrap()
+        );
+        assert_eq!(
+            i64::MIN,
+            OverflowPolicy::Wrap.add(i64::MAX, 1, full()).unwrap()
+        );
+        assert_eq!(u32::MAX, OverflowPolicy::Wrap.sub(0u32, 1, full()).unwrap());
+    }
+
+    #[test]
+    fn should_handle_signed_values() {
+        let policy = OverflowPolicy::WrapTo(-10i64);
+        assert_eq!(-9, policy.add(i64::MAX - 1, 3, full()).unwrap());
+        assert_eq!(-5, policy.add(-10, 5, full()).unwrap());
+        assert_eq!(i64::MAX - 1, policy.sub(i64::MIN + 1, 3, full()).unwrap());
+        assert_eq!(
+            i64::MIN,
+            OverflowPolicy::Saturate
+                .sub(i64::MIN + 1, 3, full())
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn should_reject_negative_increments() {
+        for policy in [
+            OverflowPolicy::Error,
+            OverflowPolicy::Saturate,
+            OverflowPolicy::Wrap,
+            OverflowPolicy::WrapTo(-10i64),
+        ] {
+            let result = policy.add(i64::MAX, -1, full());
+            assert!(matches!(result, Err(FileSeqError::Overflow)));
+            let result = policy.add(0, -1, full());
+            assert!(matches!(result, Err(FileSeqError::Overflow)));
+            let result = policy.sub(0, -1, full());
+            assert!(matches!(result, Err(FileSeqError::Overflow)));
+            let result = policy.block(10, -5, full());
+            assert!(matches!(result, Err(FileSeqError::Overflow)));
+        }
+    }
+
+    #[test]
+    fn should_wrap_to_min_on_overflow() {
+        let policy = OverflowPolicy::WrapTo(10);
+        assert_eq!(10, policy.add(u64::MAX, 1, full()).unwrap());
+        assert_eq!(13, policy.add(u64::MAX - 1, 5, full()).unwrap());
+        // Wraps around the remaining range more than once
+        let range = u64::MAX - 10 + 1;
+        assert_eq!(11, policy.add(u64::MAX, range + 2, full()).unwrap());
+    }
+
+    #[test]
+    fn should_handle_underflow() {
+        assert_eq!(1, OverflowPolicy::Error.sub(3u64, 2, full()).unwrap());
+        assert!(matches!(
+            OverflowPolicy::Error.sub(0u64, 1, full()),
+            Err(FileSeqError::Overflow)
+        ));
+        assert_eq!(0, OverflowPolicy::Saturate.sub(1u64, 5, full()).unwrap());
+        assert_eq!(u64::MAX, OverflowPolicy::Wrap.sub(0, 1, full()).unwrap());
+        assert_eq!(
+            u64::MAX - 1,
+            OverflowPolicy::WrapTo(10).sub(1, 3, full()).unwrap()
+        );
+    }
+
+    #[test]
+    fn should_not_split_blocks() {
+        assert_eq!(
+            5..15,
+            OverflowPolicy::Error.block(5u64, 10, full()).unwrap()
+        );
+        let result = OverflowPolicy::Error.block(u64::MAX - 5, 10, full());
+        assert!(matches!(result, Err(FileSeqError::Overflow)));
+        let saturated = OverflowPolicy::Saturate
+            .block(u64::MAX - 5, 10, full())
+            .unwrap();
+        assert_eq!(u64::MAX - 5..u64::MAX, saturated);
+        assert_eq!(
+            0..10,
+            OverflowPolicy::Wrap
+                .block(u64::MAX - 5, 10, full())
+                .unwrap()
+        );
+        assert_eq!(
+            7..17,
+            OverflowPolicy::WrapTo(7)
+                .block(u64::MAX - 5, 10, full())
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn should_respect_bounds() {
+        let bounds = Bounds {
+            min: 1u64,
+            max: 999,
+        };
+        assert_eq!(999, OverflowPolicy::Error.add(998, 1, bounds).unwrap());
+        let result = OverflowPolicy::Error.add(999, 1, bounds);
+        assert!(matches!(result, Err(FileSeqError::Overflow)));
+        assert_eq!(999, OverflowPolicy::Saturate.add(998, 5, bounds).unwrap());
+        assert_eq!(1, OverflowPolicy::Wrap.add(999, 1, bounds).unwrap());
+        assert_eq!(4, OverflowPolicy::Wrap.add(998, 5, bounds).unwrap());
+        assert_eq!(1, OverflowPolicy::Saturate.sub(3, 5, bounds).unwrap());
+        assert_eq!(999, OverflowPolicy::Wrap.sub(1, 1, bounds).unwrap());
+        assert_eq!(1..11, OverflowPolicy::Wrap.block(995, 10, bounds).unwrap());
+        assert_eq!(
+            995..999,
+            OverflowPolicy::Saturate.block(995, 10, bounds).unwrap()
+        );
+    }
+}
@@ -0,0 +1,161 @@
+use std::path::Path;
+
+use crate::error::{FileSeqError, Result};
+use crate::files::{self, FileState};
+use crate::metadata::{self, Metadata};
+use crate::storage::{FsStorage, Layout, SeqStorage, Slot};
+use crate::value::SeqValue;
+use crate::verify::VerifyReport;
+
+/// A handle that only observes a sequence.
+///
+/// Unlike [`FileSeq`](crate::FileSeq), it never creates, locks or removes
+/// files, so it works on read-only mounts and snapshots, and can't change a
+/// sequence it's only meant to watch.
+///
+/// Without the lock, a read racing with a write of another process returns
+/// either the value from before that write or a newer one.
+///
+/// # Example
+///
+/// ```
+/// use file_seq::FileSeq;
+/// use std::path::Path;
+///
+/// let dir = Path::new("/tmp/example_read_only");
+/// # let _ = std::fs::remove_dir_all(&dir);
+///
+/// let seq = FileSeq::new(&dir, 1).unwrap();
+/// let observer = FileSeq::open_read_only(&dir).unwrap();
+///
+/// seq.increment_and_get(1).unwrap();
+/// assert_eq!(2, observer.value().unwrap());
+/// ```
+#[derive(Debug, Clone)]
+pub struct ReadOnlyFileSeq<T = u64> {
+    storage: FsStorage,
+    versions: u32,
+    _value: std::marker::PhantomData<T>,
+}
+
+impl<T: SeqValue> ReadOnlyFileSeq<T> {
+    /// Opens the sequence stored under `name` in `store_dir`.
+    pub(crate) fn open<P: AsRef<Path>>(store_dir: P, name: &str) -> Result<Self> {
+        let storage = FsStorage::new(store_dir, name);
+        let versions = match Metadata::<T>::read(&storage)? {
+            Some(metadata) => {
+                metadata.check_layout(Layout::Files)?;
+                metadata.versions
+            }
+            None => metadata::found_versions(&storage),
+        };
+        let seq = Self {
+            storage,
+            versions,
+            _value: std::marker::PhantomData,
+        };
+        if !(1..=versions).any(|n| seq.storage.exists(Slot::Version(n))) {
+            return Err(FileSeqError::NotFound);
+        }
+        Ok(seq)
+    }
+
+    /// Returns the current value of the sequence.
+    ///
+    /// A corrupted latest file is skipped, but not removed.
+    pub fn value(&self) -> Result<T> {
+        // Newest first, as writers rotate the versions towards the older
+        // ones. A version read later never holds an older value than the
+        // newer ones read before it held, even if a write renamed it meanwhile.
+        let mut versions = Vec::new();
+        for n in (1..=self.versions).rev() {
+            let state = FileState::from_contents(self.storage.read(Slot::Version(n)));
+            let valid = matches!(state, FileState::Valid(_));
+            versions.push(state);
+            if valid {
+                break;
+            }
+        }
+        versions.reverse();
+        Ok(files::recover(&versions)?.value)
+    }
+
+    /// See [`FileSeq::verify`](crate::FileSeq::verify).
+    pub fn verify(&self) -> Result<VerifyReport<T>> {
+        Ok(VerifyReport::read(&self.storage, self.versions))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::fs;
+    use std::path::Path;
+
+    use crate::tests::tmpdir;
+    use crate::{FileSeq, FileSeqError, FileStatus, ReadOnlyFileSeq};
+
+    /// Returns the names and contents of all files in `dir`.
+    fn snapshot(dir: &Path) -> BTreeMap<String, Vec<u8>> {
+        fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| {
+                let entry = entry.unwrap();
+                let name = entry.file_name().into_string().unwrap();
+                (name, fs::read(entry.path()).unwrap())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn should_read_value() {
+        let dir = tmpdir();
+        let seq = FileSeq::new(&dir, 1).unwrap();
+        let observer = FileSeq::open_read_only(&dir).unwrap();
+        assert_eq!(1, observer.value().unwrap());
+        seq.increment_and_get(1).unwrap();
+        assert_eq!(2, observer.value().unwrap());
+    }
+
+    #[test]
+    fn should_not_open_missing_sequence() {
+        let dir = tmpdir();
+        let result = FileSeq::open_read_only(&dir);
+        assert!(matches!(result, Err(FileSeqError::NotFound)));
+        let result = FileSeq::open_read_only(dir.join("missing"));
+        assert!(matches!(result, Err(FileSeqError::NotFound)));
+        assert!(snapshot(&dir).is_empty());
+    }
+
+    #[test]
+    fn should_not_modify_store() {
+        let dir = tmpdir();
+        let seq = FileSeq::new(&dir, 1).unwrap();
+        seq.increment_and_get(1).unwrap();
+        fs::write(dir.join("_2.seq"), [0xff; 12]).unwrap();
+        fs::remove_file(dir.join(".lock")).unwrap();
+        let before = snapshot(&dir);
+
+        let observer = FileSeq::open_read_only(&dir).unwrap();
+        assert_eq!(1, observer.value().unwrap());
+        let report = observer.verify().unwrap();
+        assert!(matches!(report.latest.status, FileStatus::Corrupted { .. }));
+        assert_eq!(before, snapshot(&dir));
+    }
+
+    #[test]
+    fn should_open_named_typed_sequence() {
+        let dir = tmpdir();
+        FileSeq::builder(&dir).name("orders").build().unwrap();
+        let observer: ReadOnlyFileSeq<u64> = FileSeq::builder(&dir)
+            .name("orders")
+            .build_read_only()
+            .unwrap();
+        assert_eq!(0, observer.value().unwrap());
+
+        let dir = tmpdir();
+        FileSeq::new_typed(&dir, -1i64).unwrap();
+        let observer = FileSeq::<i64>::open_read_only_typed(&dir).unwrap();
+        assert_eq!(-1, observer.value().unwrap());
+    }
+}
@@ -0,0 +1,288 @@
+//! On-disk format of a sequence value.
+//!
+//! A record is laid out as:
+//!
+//! | bytes | content                                      |
+//! |-------|----------------------------------------------|
+//! | 4     | magic `FSEQ`                                 |
+//! | 1     | format version                               |
+//! | 1     | length `n` of the value                      |
+//! | n     | value, as encoded by `SeqValue::encode`      |
+//! | 4     | CRC32 of all previous bytes, big-endian      |
+//!
+//! Files written before the record format existed hold only the 8 value
+//! bytes of a `u64`. Those are still accepted for 8 byte value types, and
+//! replaced by a record on the next write.
+//!
+//! The text format holds two lines, the decimal value and the CRC32 of that
+//! value's text, in hex:
+//!
+//! ```text
+//! 42
+//! crc32 3224b088
+//! ```
+//!
+//! Decoding tells the formats apart by the magic, so files of either format
+//! can be read regardless of the codec a sequence writes with.
+
+use std::io::{Error, ErrorKind};
+
+use crate::value::SeqValue;
+
+/// The format values are written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Codec {
+    /// A compact binary record with a checksum.
+    #[default]
+    Binary,
+    /// The decimal value and a checksum line, so the files can be read with
+    /// `cat` and fixed by hand.
+    Text,
+}
+
+impl Codec {
+    pub(crate) fn encode<T: SeqValue>(self, value: T) -> Vec<u8> {
+        match self {
+            Codec::Binary => encode(value),
+            Codec::Text => encode_text(value),
+        }
+    }
+}
+
+const TEXT_CHECKSUM_PREFIX: &str = "crc32 ";
+
+const MAGIC: [u8; 4] = *b"FSEQ";
+const VERSION: u8 = 1;
+const HEADER_LEN: usize = MAGIC.len() + 2;
+const CHECKSUM_LEN: usize = 4;
+const LEGACY_LEN: usize = 8;
+
+pub(crate) fn encode<T: SeqValue>(value: T) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(HEADER_LEN + 16 + CHECKSUM_LEN);
+    bytes.extend_from_slice(&MAGIC);
+    bytes.push(VERSION);
+    bytes.push(0);
+    value.encode(&mut bytes);
+    let len = bytes.len() - HEADER_LEN;
+    assert!(len <= usize::from(u8::MAX), "encoded value is too long");
+    bytes[HEADER_LEN - 1] = len as u8;
+    let checksum = crc32fast::hash(&bytes);
+    bytes.extend_from_slice(&checksum.to_be_bytes());
+    bytes
+}
+
+fn encode_text<T: SeqValue>(value: T) -> Vec<u8> {
+    let value = value.to_string();
+    let checksum = crc32fast::hash(value.as_bytes());
+    format!("{}\n{}{:08x}\n", value, TEXT_CHECKSUM_PREFIX, checksum).into_bytes()
+}
+
+/// Decodes a value written with any [`Codec`].
+///
+/// Fails with `ErrorKind::UnexpectedEof` if the bytes are the beginning of a
+/// record, `ErrorKind::InvalidData` if they aren't a valid record at all.
+pub(crate) fn decode<T: SeqValue>(bytes: &[u8]) -> std::io::Result<T> {
+    if bytes.len() == LEGACY_LEN && !bytes.starts_with(&MAGIC) && !is_text(bytes) {
+        return T::decode(bytes).ok_or_else(|| invalid("Sequence file has an unknown format."));
+    }
+    if MAGIC.starts_with(bytes) {
+        return Err(truncated());
+    }
+    if !bytes.starts_with(&MAGIC) {
+        return decode_text(bytes);
+    }
+    if bytes.len() < HEADER_LEN + CHECKSUM_LEN {
+        return Err(truncated());
+    }
+    if bytes.len() < HEADER_LEN + usize::from(bytes[HEADER_LEN - 1]) + CHECKSUM_LEN {
+        return Err(truncated());
+    }
+
+    let (content, checksum) = bytes.split_at(bytes.len() - CHECKSUM_LEN);
+    if crc32fast::hash(content).to_be_bytes() != checksum {
+        return Err(invalid("Sequence file checksum mismatch."));
+    }
+
+    let version = content[MAGIC.len()];
+    if version != VERSION {
+        return Err(invalid("Sequence file has an unsupported format version."));
+    }
+    let value = &content[HEADER_LEN..];
+    if value.len() != usize::from(content[MAGIC.len() + 1]) {
+        return Err(invalid("Sequence file has an unexpected value length."));
+    }
+    T::decode(value).ok_or_else(|| invalid("Sequence file holds a value of another type."))
+}
+
+/// Returns whether `bytes` are the beginning of a text record, a number
+/// optionally followed by a newline and the start of the checksum line,
+/// rather than a legacy value.
+fn is_text(bytes: &[u8]) -> bool {
+    let (value, rest) = match bytes.iter().position(|&b| b == b'\n') {
+        Some(i) => (&bytes[..i], &bytes[i + 1..]),
+        None => (bytes, &[][..]),
+    };
+    let digits = value.strip_prefix(b"-").unwrap_or(value);
+    !digits.is_empty()
+        && digits.iter().all(u8::is_ascii_digit)
+        && TEXT_CHECKSUM_PREFIX.as_bytes().starts_with(rest)
+}
+
+fn decode_text<T: SeqValue>(bytes: &[u8]) -> std::io::Result<T> {
+    let text =
+        std::str::from_utf8(bytes).map_err(|_| invalid("Sequence file has an unknown format."))?;
+    let mut lines = text.lines().map(str::trim).filter(|line| !line.is_empty());
+    let (value, checksum) = match (lines.next(), lines.next(), lines.next()) {
+        (Some(value), Some(checksum), None) => (value, checksum),
+        (Some(_), None, None) => return Err(truncated()),
+        _ => return Err(invalid("Sequence file has an unknown format.")),
+    };
+
+    if TEXT_CHECKSUM_PREFIX.starts_with(checksum) {
+        return Err(truncated());
+    }
+    let checksum = checksum
+        .strip_prefix(TEXT_CHECKSUM_PREFIX)
+        .and_then(|checksum| u32::from_str_radix(checksum.trim(), 16).ok())
+        .ok_or_else(|| invalid("Sequence file has an unknown format."))?;
+    if crc32fast::hash(value.as_bytes()) != checksum {
+        return Err(invalid("Sequence file checksum mismatch."));
+    }
+    value
+        .parse()
+        .map_err(|_| invalid("Sequence file holds a value of another type."))
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+fn truncated() -> Error {
+    Error::new(ErrorKind::UnexpectedEof, "Sequence file is truncated.")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::ErrorKind;
+
+    use crate::record::{decode, encode, Codec};
+
+    #[test]
+    fn should_round_trip() {
+        for value in [0, 1, 42, u64::MAX] {
+            assert_eq!(value, decode::<u64>(&encode(value)).unwrap());
+        }
+        assert_eq!(-1, decode::<i64>(&encode(-1i64)).unwrap());
+        assert_eq!(u128::MAX, decode::<u128>(&encode(u128::MAX)).unwrap());
+    }
+
+    #[test]
+    fn should_reject_value_of_other_type() {
+        assert!(decode::<u32>(&encode(42u64)).is_err());
+        assert!(decode::<u64>(&encode(42u128)).is_err());
+    }
+
+    #[test]
+    fn should_read_legacy_value() {
+        assert_eq!(42, decode::<u64>(&42u64.to_be_bytes()).unwrap());
+        assert!(decode::<u32>(&42u64.to_be_bytes()).is_err());
+    }
+
+    #[test]
+    fn should_reject_flipped_bit() {
+        let record = encode(42u64);
+        for i in 0..record.len() {
+            let mut corrupted = record.clone();
+            corrupted[i] ^= 0x10;
+            assert!(
+                decode::<u64>(&corrupted).is_err(),
+                "flip in byte {} went unnoticed",
+                i
+            );
+        }
+    }
+
+    #[test]
+    fn should_reject_truncated_record() {
+        let record = encode(42u64);
+        for len in 0..record.len() {
+            let err = decode::<u64>(&record[..len]).unwrap_err();
+            assert_eq!(ErrorKind::UnexpectedEof, err.kind(), "length {}", len);
+        }
+        let err = decode::<u64>(b"42\n").unwrap_err();
+        assert_eq!(ErrorKind::UnexpectedEof, err.kind());
+        let err = decode::<u64>(b"12345\ncr").unwrap_err();
+        assert_eq!(ErrorKind::UnexpectedEof, err.kind());
+    }
+
+    #[test]
+    fn should_not_read_truncated_text_as_legacy_value() {
+        let record = Codec::Text.encode(1234567890u64);
+        for len in 0..record.len() {
+            // Only the final newline can be cut off without losing anything
+            let decoded = decode::<u64>(&record[..len]);
+            assert!(
+                decoded.map_or(true, |value| value == 1234567890),
+                "length {}",
+                len
+            );
+        }
+        assert!(decode::<u64>(b"12345\ncr").is_err());
+        assert!(decode::<u64>(b"1\ncrc32 ").is_err());
+        assert!(decode::<i64>(b"-1\ncrc32").is_err());
+    }
+
+    #[test]
+    fn should_read_legacy_values_with_newline_byte() {
+        for value in [10u64, 266, 2570, 0x0a0a_0a0a_0a0a_0a0a] {
+            assert_eq!(value, decode::<u64>(&value.to_be_bytes()).unwrap());
+        }
+    }
+
+    #[test]
+    fn should_reject_unknown_version() {
+        let mut record = encode(42u64);
+        record[4] = 2;
+        let len = record.len();
+        let checksum = crc32fast::hash(&record[..len - 4]);
+        record[len - 4..].copy_from_slice(&checksum.to_be_bytes());
+        assert!(decode::<u64>(&record).is_err());
+    }
+
+    #[test]
+    fn should_round_trip_text() {
+        for value in [0, 1, 42, u64::MAX] {
+            let record = Codec::Text.encode(value);
+            assert_eq!(value, decode::<u64>(&record).unwrap());
+        }
+        let record = Codec::Text.encode(-1i64);
+        assert_eq!(-1, decode::<i64>(&record).unwrap());
+    }
+
+    #[test]
+    fn should_write_readable_text() {
+        assert_eq!(b"42\ncrc32 3224b088\n".to_vec(), Codec::Text.encode(42u64));
+    }
+
+    #[test]
+    fn should_accept_hand_edited_text() {
+        assert_eq!(42, decode::<u64>(b"  42\r\ncrc32 3224B088\r\n\n").unwrap());
+        assert_eq!(42, decode::<u64>(b"42\ncrc32 3224b088").unwrap());
+    }
+
+    #[test]
+    fn should_reject_invalid_text() {
+        for record in [
+            &b"43\ncrc32 3224b088\n"[..],
+            b"42\n",
+            b"42\ncrc32 zz\n",
+            b"42\n3224b088\n",
+            b"42\ncrc32 3224b088\n1\n",
+            b"\xff\xfe\xfd\xfc\xfb\xfa\xf9\xf8\xf7",
+        ] {
+            assert!(decode::<u64>(record).is_err(), "{:?}", record);
+        }
+        assert!(decode::<u64>(&Codec::Text.encode(-1i64)).is_err());
+        assert!(decode::<u32>(&Codec::Text.encode(u64::MAX)).is_err());
+    }
+}
@@ -0,0 +1,561 @@
+//! A sequence kept in the fixed-size slots of a single file.
+
+use std::convert::TryInto;
+use std::fs::{self, File};
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use crate::durability::Durability;
+use crate::files;
+use crate::storage::{FsStorage, Layout, SeqStorage, Slot};
+
+/// Size of a slot, so every slot fills a disk sector of its own.
+const SLOT_SIZE: usize = 512;
+/// The generation, the length of the contents and the checksum.
+const HEADER_SIZE: usize = 16;
+
+/// What a slot of the ring file holds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum RingSlot {
+    /// Never written, or removed.
+    Empty,
+    /// Torn or garbled, e.g. by a crash while it was written.
+    Corrupted,
+    Valid {
+        generation: u64,
+        contents: Vec<u8>,
+    },
+}
+
+impl RingSlot {
+    fn encode(generation: u64, contents: &[u8]) -> Vec<u8> {
+        let mut slot = Vec::with_capacity(SLOT_SIZE);
+        slot.extend_from_slice(&generation.to_be_bytes());
+        slot.extend_from_slice(&(contents.len() as u32).to_be_bytes());
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&slot);
+        hasher.update(contents);
+        slot.extend_from_slice(&hasher.finalize().to_be_bytes());
+        slot.extend_from_slice(contents);
+        slot.resize(SLOT_SIZE, 0);
+        slot
+    }
+
+    fn decode(slot: &[u8]) -> Self {
+        if slot.len() != SLOT_SIZE {
+            return RingSlot::Corrupted;
+        }
+        if slot.iter().all(|&b| b == 0) {
+            return RingSlot::Empty;
+        }
+        let generation = u64::from_be_bytes(slot[..8].try_into().unwrap());
+        let len = u32::from_be_bytes(slot[8..12].try_into().unwrap()) as usize;
+        let checksum = u32::from_be_bytes(slot[12..HEADER_SIZE].try_into().unwrap());
+        if generation == 0 || len > SLOT_SIZE - HEADER_SIZE {
+            return RingSlot::Corrupted;
+        }
+        let contents = &slot[HEADER_SIZE..HEADER_SIZE + len];
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&slot[..12]);
+        hasher.update(contents);
+        if hasher.finalize() != checksum {
+            return RingSlot::Corrupted;
+        }
+        RingSlot::Valid {
+            generation,
+            contents: contents.to_vec(),
+        }
+    }
+}
+
+/// Keeps a sequence in a single preallocated file with one fixed-size slot
+/// per version, `.ring`, instead of one file per version.
+///
+/// Every slot carries a generation counter and a checksum. Writes go to the
+/// slot after the one with the highest generation, in place and synced with
+/// `fdatasync`, and reads pick the valid slot with the highest generation. A
+/// crash while writing leaves that slot torn, so the value is read from the
+/// previous one, like a missing latest file. Unlike [`FsStorage`], no file
+/// is created or renamed once the sequence exists, which spares the
+/// filesystem a metadata update on every write.
+///
+/// The metadata and lock files are kept next to it as usual. The
+/// [`Layout`] is recorded in the metadata, so
+/// opening the sequence with [`FsStorage`], e.g. with
+/// [`ReadOnlyFileSeq`](crate::ReadOnlyFileSeq), [`SeqStore`](crate::SeqStore)
+/// or the command-line tool, fails with
+/// [`FileSeqError::InvalidConfig`](crate::FileSeqError::InvalidConfig).
+///
+/// # Example
+///
+/// ```
+/// use file_seq::{FileSeqBuilder, RingStorage};
+/// use std::path::Path;
+///
+/// let dir = Path::new("/tmp/example_ring");
+/// # let _ = std::fs::remove_dir_all(&dir);
+/// std::fs::create_dir_all(&dir).unwrap();
+///
+/// let seq = FileSeqBuilder::new(&dir)
+///     .initial_value(1)
+///     .build_with_storage(RingStorage::new(&dir, "orders"))
+///     .unwrap();
+/// assert_eq!(2, seq.increment_and_get(1).unwrap());
+///
+/// assert_eq!(1024, std::fs::metadata(dir.join("orders.ring")).unwrap().len());
+/// assert!(std::fs::metadata(dir.join("orders_2.seq")).is_err());
+/// ```
+#[derive(Debug, Clone)]
+pub struct RingStorage {
+    /// Keeps the metadata, which is rarely written.
+    fs: FsStorage,
+}
+
+impl RingStorage {
+    /// Keeps the sequence stored under `name` in `store_dir`, the unnamed one
+    /// if `name` is empty.
+    pub fn new<P: AsRef<Path>>(store_dir: P, name: &str) -> Self {
+        Self {
+            fs: FsStorage::new(store_dir, name),
+        }
+    }
+
+    fn ring_path(&self) -> &Path {
+        &self.fs.files.ring_path
+    }
+
+    fn slots(&self) -> io::Result<Vec<RingSlot>> {
+        match fs::read(self.ring_path()) {
+            Ok(bytes) => Ok(bytes.chunks(SLOT_SIZE).map(RingSlot::decode).collect()),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Returns the slots and the index of the one holding version `n`.
+    ///
+    /// Valid slots are ordered by generation, corrupted ones are taken for
+    /// newer versions, as a slot is only corrupted by a write that didn't
+    /// complete. Versions older than all used slots are missing.
+    fn locate(&self, n: u32) -> io::Result<(Vec<RingSlot>, usize)> {
+        let slots = self.slots()?;
+        let mut valid: Vec<_> = slots
+            .iter()
+            .enumerate()
+            .filter_map(|(i, slot)| match slot {
+                RingSlot::Valid { generation, .. } => Some((*generation, i)),
+                _ => None,
+            })
+            .collect();
+        valid.sort_unstable();
+        let used: Vec<_> = valid
+            .into_iter()
+            .map(|(_, i)| i)
+            .chain((0..slots.len()).filter(|&i| slots[i] == RingSlot::Corrupted))
+            .collect();
+
+        let n = n as usize;
+        let missing = slots.len() - used.len();
+        if n <= missing || n > slots.len() {
+            return Err(not_found(Slot::Version(n as u32)));
+        }
+        let index = used[n - 1 - missing];
+        Ok((slots, index))
+    }
+
+    fn open(&self, create: bool) -> io::Result<File> {
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(create);
+        files::set_file_mode(&mut options, self.fs.file_mode);
+        options.open(self.ring_path())
+    }
+
+    /// Writes `contents` to version `n` in place, keeping its generation.
+    fn overwrite(&self, n: u32, contents: &[u8], durability: Durability) -> io::Result<()> {
+        check_len(contents)?;
+        let (slots, index) = self.locate(n)?;
+        let generation = match slots[index] {
+            RingSlot::Valid { generation, .. } => generation,
+            _ => {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("the slot of version {} is corrupted", n),
+                ))
+            }
+        };
+        let f = self.open(false)?;
+        write_at(&f, &RingSlot::encode(generation, contents), index)?;
+        durability.sync_data(&f)
+    }
+}
+
+fn not_found(slot: Slot) -> io::Error {
+    io::Error::new(ErrorKind::NotFound, format!("no {} slot", slot))
+}
+
+fn unsupported(slot: Slot) -> io::Error {
+    io::Error::new(
+        ErrorKind::Unsupported,
+        format!("the {} slot isn't kept by a ring storage", slot),
+    )
+}
+
+fn check_len(contents: &[u8]) -> io::Result<()> {
+    if contents.len() > SLOT_SIZE - HEADER_SIZE {
+        return Err(io::Error::new(
+            ErrorKind::InvalidInput,
+            "contents don't fit in a slot",
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn write_at(f: &File, slot: &[u8], index: usize) -> io::Result<()> {
+    use std::os::unix::fs::FileExt;
+
+    f.write_all_at(slot, (index * SLOT_SIZE) as u64)
+}
+
+#[cfg(not(unix))]
+fn write_at(mut f: &File, slot: &[u8], index: usize) -> io::Result<()> {
+    use std::io::{Seek, SeekFrom, Write};
+
+    f.seek(SeekFrom::Start((index * SLOT_SIZE) as u64))?;
+    f.write_all(slot)
+}
+
+impl SeqStorage for RingStorage {
+    fn read(&self, slot: Slot) -> io::Result<Vec<u8>> {
+        match slot {
+            Slot::Version(n) => {
+                let (mut slots, index) = self.locate(n)?;
+                match slots.swap_remove(index) {
+                    RingSlot::Valid { contents, .. } => Ok(contents),
+                    _ => Err(io::Error::new(
+                        ErrorKind::InvalidData,
+                        format!("slot {} of the ring file is corrupted", index),
+                    )),
+                }
+            }
+            Slot::Pending => Err(not_found(slot)),
+            _ => self.fs.read(slot),
+        }
+    }
+
+    /// Versions are overwritten in place, only existing ones can be written.
+    fn write(&self, slot: Slot, contents: &[u8], durability: Durability) -> io::Result<()> {
+        match slot {
+            Slot::Version(n) => self.overwrite(n, contents, durability),
+            Slot::Pending => Err(unsupported(slot)),
+            _ => self.fs.write(slot, contents, durability),
+        }
+    }
+
+    fn rename(&self, from: Slot, to: Slot) -> io::Result<()> {
+        match (from, to) {
+            (Slot::PendingMetadata | Slot::Metadata, Slot::PendingMetadata | Slot::Metadata) => {
+                self.fs.rename(from, to)
+            }
+            (Slot::Version(_) | Slot::Pending, _) => Err(unsupported(from)),
+            _ => Err(unsupported(to)),
+        }
+    }
+
+    /// Clears the slot of a version, and removes the ring file once every
+    /// slot is cleared.
+    fn remove(&self, slot: Slot) -> io::Result<()> {
+        let n = match slot {
+            Slot::Version(n) => n,
+            Slot::Pending => return Err(not_found(slot)),
+            _ => return self.fs.remove(slot),
+        };
+        let (mut slots, index) = self.locate(n)?;
+        write_at(&self.open(false)?, &[0; SLOT_SIZE], index)?;
+        slots[index] = RingSlot::Empty;
+        if slots.iter().all(|slot| *slot == RingSlot::Empty) {
+            fs::remove_file(self.ring_path())?;
+        }
+        Ok(())
+    }
+
+    fn exists(&self, slot: Slot) -> bool {
+        match slot {
+            Slot::Version(n) => self.locate(n).is_ok(),
+            Slot::Pending => false,
+            _ => self.fs.exists(slot),
+        }
+    }
+
+    fn sync(&self, durability: Durability) -> io::Result<()> {
+        self.fs.sync(durability)
+    }
+
+    /// Writes `contents` with the next generation to the slot after the
+    /// newest one, creating the ring file with `versions` slots first if
+    /// necessary.
+    fn push_version(
+        &self,
+        contents: &[u8],
+        versions: u32,
+        durability: Durability,
+    ) -> io::Result<()> {
+        check_len(contents)?;
+        let slots = self.slots()?;
+        let len = slots.len().max(versions as usize);
+        let newest = slots
+            .iter()
+            .enumerate()
+            .filter_map(|(i, slot)| match slot {
+                RingSlot::Valid { generation, .. } => Some((*generation, i)),
+                _ => None,
+            })
+            .max();
+        let (generation, index) = match newest {
+            Some((generation, i)) => (generation + 1, (i + 1) % len),
+            None => (1, 0),
+        };
+
+        let f = self.open(true)?;
+        if slots.len() < len {
+            // Preallocated, so later writes don't change the size
+            f.set_len((len * SLOT_SIZE) as u64)?;
+            write_at(&f, &RingSlot::encode(generation, contents), index)?;
+            durability.sync_file(&f)?;
+            return self.fs.sync(durability);
+        }
+        write_at(&f, &RingSlot::encode(generation, contents), index)?;
+        durability.sync_data(&f)
+    }
+
+    /// The newest valid version is replaced by pushing the contents with the
+    /// next generation, so a crash leaves either the previous or the new
+    /// contents as the newest valid slot. Older versions are overwritten in
+    /// place, a torn one is never read while a newer slot is valid.
+    fn replace(&self, slot: Slot, contents: &[u8], durability: Durability) -> io::Result<()> {
+        let n = match slot {
+            Slot::Version(n) => n,
+            _ => return self.fs.replace(slot, contents, durability),
+        };
+        let (slots, index) = self.locate(n)?;
+        let newest = slots
+            .iter()
+            .filter_map(|slot| match slot {
+                RingSlot::Valid { generation, .. } => Some(*generation),
+                _ => None,
+            })
+            .max();
+        match &slots[index] {
+            RingSlot::Valid { generation, .. } if Some(*generation) == newest => {
+                self.push_version(contents, slots.len() as u32, durability)
+            }
+            _ => self.overwrite(n, contents, durability),
+        }
+    }
+
+    fn layout(&self) -> Layout {
+        Layout::Ring
+    }
+
+    fn lock_path(&self) -> Option<&Path> {
+        self.fs.lock_path()
+    }
+
+    fn path(&self, slot: Slot) -> PathBuf {
+        match slot {
+            Slot::Version(_) | Slot::Pending => self.ring_path().to_path_buf(),
+            _ => self.fs.path(slot),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use crate::ring::{RingSlot, RingStorage, HEADER_SIZE, SLOT_SIZE};
+    use crate::storage::{Layout, SeqStorage, Slot};
+    use crate::tests::tmpdir;
+    use crate::{Codec, FileSeq, FileSeqBuilder, FileSeqError, FileStatus, SeqStore, Version};
+
+    fn open(dir: &Path, versions: u32) -> FileSeq<u64, RingStorage> {
+        FileSeqBuilder::new(dir)
+            .initial_value(1)
+            .versions(versions)
+            .build_with_storage(RingStorage::new(dir, "orders"))
+            .unwrap()
+    }
+
+    fn file_names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn should_round_trip_slots() {
+        let slot = RingSlot::encode(7, b"42");
+        assert_eq!(SLOT_SIZE, slot.len());
+        assert_eq!(
+            RingSlot::Valid {
+                generation: 7,
+                contents: b"42".to_vec()
+            },
+            RingSlot::decode(&slot)
+        );
+        assert_eq!(RingSlot::Empty, RingSlot::decode(&[0; SLOT_SIZE]));
+        assert_eq!(RingSlot::Corrupted, RingSlot::decode(&slot[..100]));
+        for i in 0..HEADER_SIZE + 2 {
+            let mut garbled = slot.clone();
+            garbled[i] ^= 1;
+            assert_eq!(RingSlot::Corrupted, RingSlot::decode(&garbled), "{}", i);
+        }
+    }
+
+    #[test]
+    fn should_keep_sequence_in_one_file() {
+        let dir = tmpdir();
+        let seq = open(&dir, 3);
+        for value in 2..=10 {
+            assert_eq!(value, seq.increment_and_get(1).unwrap());
+        }
+        assert_eq!(
+            vec!["orders.lock", "orders.meta", "orders.ring"],
+            file_names(&dir)
+        );
+        let ring = fs::metadata(dir.join("orders.ring")).unwrap();
+        assert_eq!(3 * SLOT_SIZE as u64, ring.len());
+
+        let report = seq.verify().unwrap();
+        assert_eq!(FileStatus::Valid(8), report.older[0].status);
+        assert_eq!(FileStatus::Valid(9), report.backup.status);
+        assert_eq!(Some(10), report.value());
+        assert_eq!(10, open(&dir, 3).value().unwrap());
+
+        seq.delete().unwrap();
+        assert_eq!(vec!["orders.lock"], file_names(&dir));
+    }
+
+    #[test]
+    fn should_read_slot_with_highest_generation() {
+        let dir = tmpdir();
+        let seq = open(&dir, 2);
+        seq.increment_and_get(1).unwrap();
+        seq.increment_and_get(1).unwrap();
+
+        // The third write wrapped around to the first slot
+        let bytes = fs::read(dir.join("orders.ring")).unwrap();
+        assert_eq!(
+            RingSlot::Valid {
+                generation: 3,
+                contents: Codec::Binary.encode(3u64)
+            },
+            RingSlot::decode(&bytes[..SLOT_SIZE])
+        );
+        assert_eq!(3, seq.value().unwrap());
+    }
+
+    #[test]
+    fn should_fall_back_to_previous_slot_when_write_is_torn() {
+        let dir = tmpdir();
+        let seq = open(&dir, 2);
+        seq.increment_and_get(1).unwrap();
+        let before = fs::read(dir.join("orders.ring")).unwrap();
+        seq.increment_and_get(1).unwrap();
+        let after = fs::read(dir.join("orders.ring")).unwrap();
+
+        for torn in 0..=SLOT_SIZE {
+            let mut bytes = before.clone();
+            bytes[..torn].copy_from_slice(&after[..torn]);
+            fs::write(dir.join("orders.ring"), &bytes).unwrap();
+
+            let value = seq.value().unwrap();
+            assert!(value == 2 || value == 3, "{} bytes: {}", torn, value);
+            assert_eq!(value + 1, seq.increment_and_get(1).unwrap());
+            assert_eq!(value + 1, seq.value().unwrap());
+        }
+    }
+
+    #[test]
+    fn should_report_and_repair_corrupted_slot() {
+        let dir = tmpdir();
+        let seq = open(&dir, 2);
+        seq.increment_and_get(1).unwrap();
+        let mut bytes = fs::read(dir.join("orders.ring")).unwrap();
+        bytes[SLOT_SIZE + 20] ^= 1;
+        fs::write(dir.join("orders.ring"), &bytes).unwrap();
+
+        let report = seq.repair().unwrap();
+        assert!(matches!(report.latest.status, FileStatus::Corrupted { .. }));
+        assert_eq!(Some(Version::Backup), report.selected);
+        assert!(!seq.verify().unwrap().needs_repair());
+        assert_eq!(1, seq.value().unwrap());
+        assert_eq!(2, seq.increment_and_get(1).unwrap());
+    }
+
+    #[test]
+    fn should_not_open_ring_sequence_with_other_layout() {
+        let dir = tmpdir();
+        open(&dir, 2).set(101).unwrap();
+        assert_eq!(Layout::Ring, open(&dir, 2).metadata().layout);
+
+        let store = SeqStore::open(&dir).unwrap();
+        for result in [
+            FileSeq::builder(&dir).name("orders").build().map(drop),
+            store.sequence("orders", 1).map(drop),
+            store.exists("orders").map(drop),
+            store.remove("orders"),
+            FileSeq::builder(&dir)
+                .name("orders")
+                .build_read_only()
+                .map(drop),
+        ] {
+            assert!(matches!(result, Err(FileSeqError::InvalidConfig(_))));
+        }
+        assert_eq!(101, open(&dir, 2).value().unwrap());
+
+        // Nor a sequence of files as a ring
+        FileSeq::builder(&dir).name("files").build().unwrap();
+        let result = FileSeqBuilder::new(&dir).build_with_storage(RingStorage::new(&dir, "files"));
+        assert!(matches!(result, Err(FileSeqError::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn should_convert_latest_slot_to_next_generation() {
+        let dir = tmpdir();
+        let mut seq = open(&dir, 2);
+        seq.increment_and_get(1).unwrap();
+        seq.convert(Codec::Text).unwrap();
+
+        // Never overwritten in place, so a torn write falls back to the same
+        // value in the previous format
+        let bytes = fs::read(dir.join("orders.ring")).unwrap();
+        assert_eq!(
+            RingSlot::Valid {
+                generation: 3,
+                contents: Codec::Text.encode(2u64)
+            },
+            RingSlot::decode(&bytes[..SLOT_SIZE])
+        );
+        assert_eq!(
+            RingSlot::Valid {
+                generation: 2,
+                contents: Codec::Binary.encode(2u64)
+            },
+            RingSlot::decode(&bytes[SLOT_SIZE..])
+        );
+
+        let storage = RingStorage::new(&dir, "orders");
+        let latest = storage.read(Slot::Version(2)).unwrap();
+        assert_eq!(
+            Some("2"),
+            std::str::from_utf8(&latest).unwrap().lines().next()
+        );
+        assert_eq!(2, seq.value().unwrap());
+        assert_eq!(3, seq.increment_and_get(1).unwrap());
+    }
+}
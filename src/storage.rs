@@ -0,0 +1,417 @@
+//! Where the files of a sequence are kept.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::durability::Durability;
+use crate::files::{self, SeqFiles};
+
+/// One of the files a sequence is stored in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Slot {
+    /// Version `n` of the value, `_n.seq`, numbered from 1 for the oldest.
+    /// With the default two versions, `Version(1)` is the backup and
+    /// `Version(2)` the latest value.
+    Version(u32),
+    /// Where the next latest value is written before it's renamed into
+    /// place, `.seq.tmp`.
+    Pending,
+    /// The [`Metadata`](crate::Metadata) of the sequence, `.meta`.
+    Metadata,
+    /// Where the metadata is written before it's renamed into place,
+    /// `.meta.tmp`.
+    PendingMetadata,
+}
+
+impl fmt::Display for Slot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Slot::Version(n) => write!(f, "version {}", n),
+            Slot::Pending => f.write_str("pending"),
+            Slot::Metadata => f.write_str("metadata"),
+            Slot::PendingMetadata => f.write_str("pending metadata"),
+        }
+    }
+}
+
+/// How the versions of a sequence are laid out, recorded in its
+/// [`Metadata`](crate::Metadata) so it's never opened with another layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum Layout {
+    /// One slot per version, `_n.seq` files for [`FsStorage`].
+    #[default]
+    Files,
+    /// The slots of a single file, see [`RingStorage`](crate::RingStorage).
+    Ring,
+}
+
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Layout::Files => f.write_str("files"),
+            Layout::Ring => f.write_str("ring"),
+        }
+    }
+}
+
+/// Keeps the slots of a sequence, e.g. as files in a directory.
+///
+/// [`FileSeq`](crate::FileSeq) only relies on these operations to rotate and
+/// recover values, so a sequence can be kept anywhere they can be provided.
+/// Renames have to be atomic: after a crash, the target slot holds either
+/// its previous or its new contents.
+///
+/// # Example
+///
+/// ```
+/// use file_seq::{FileSeqBuilder, MemoryStorage, SeqStorage, Slot};
+///
+/// let storage = MemoryStorage::new();
+/// let seq = FileSeqBuilder::new("")
+///     .initial_value(1)
+///     .build_with_storage(storage.clone())
+///     .unwrap();
+///
+/// seq.increment_and_get(1).unwrap();
+/// assert!(storage.exists(Slot::Version(1)));
+/// assert!(storage.exists(Slot::Version(2)));
+/// ```
+pub trait SeqStorage {
+    /// Returns the contents of `slot`, failing with
+    /// [`ErrorKind::NotFound`] if it doesn't exist.
+    fn read(&self, slot: Slot) -> io::Result<Vec<u8>>;
+
+    /// Creates `slot` or replaces its contents, and makes them as durable as
+    /// `durability` asks for.
+    fn write(&self, slot: Slot, contents: &[u8], durability: Durability) -> io::Result<()>;
+
+    /// Moves the contents of `from` to `to`, replacing `to`.
+    fn rename(&self, from: Slot, to: Slot) -> io::Result<()>;
+
+    /// Removes `slot`, failing with [`ErrorKind::NotFound`] if it doesn't
+    /// exist.
+    fn remove(&self, slot: Slot) -> io::Result<()>;
+
+    /// Returns whether `slot` exists.
+    fn exists(&self, slot: Slot) -> bool;
+
+    /// Makes previous renames and removals as durable as `durability` asks
+    /// for.
+    fn sync(&self, _durability: Durability) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Stores `contents` as the latest of `versions` versions, dropping the
+    /// oldest one.
+    ///
+    /// A crash has to leave either the previous or the new value as the
+    /// newest valid version. By default, the contents are fully written to
+    /// [`Slot::Pending`] before every version is renamed to the next older
+    /// one and the pending slot is renamed into place. A crash during the
+    /// rotation leaves the latest version missing, and the newest remaining
+    /// one still holds the previous value.
+    fn push_version(
+        &self,
+        contents: &[u8],
+        versions: u32,
+        durability: Durability,
+    ) -> io::Result<()> {
+        self.write(Slot::Pending, contents, durability)?;
+        for n in 2..=versions {
+            if self.exists(Slot::Version(n)) {
+                self.rename(Slot::Version(n), Slot::Version(n - 1))?;
+            }
+        }
+        self.rename(Slot::Pending, Slot::Version(versions))?;
+        self.sync(durability)
+    }
+
+    /// Replaces the contents of `slot`, by default by writing them to
+    /// [`Slot::Pending`] and renaming it to `slot`, so a crash leaves either
+    /// the previous or the new contents.
+    fn replace(&self, slot: Slot, contents: &[u8], durability: Durability) -> io::Result<()> {
+        self.write(Slot::Pending, contents, durability)?;
+        self.rename(Slot::Pending, slot)?;
+        self.sync(durability)
+    }
+
+    /// Returns how the versions are laid out, [`Layout::Files`] by default.
+    fn layout(&self) -> Layout {
+        Layout::Files
+    }
+
+    /// Returns the file other processes using the storage lock, `None` if
+    /// it's only used by this process.
+    fn lock_path(&self) -> Option<&Path> {
+        None
+    }
+
+    /// Returns the mutex that every handle on these slots locks within this
+    /// process, `None` if each handle only excludes its own threads.
+    fn shared_mutex(&self) -> Option<&Mutex<()>> {
+        None
+    }
+
+    /// Describes where `slot` is kept, for reports.
+    fn path(&self, slot: Slot) -> PathBuf {
+        PathBuf::from(slot.to_string())
+    }
+}
+
+/// Keeps a sequence in files of a directory, the storage of sequences opened
+/// with [`FileSeq::new`](crate::FileSeq::new) and the builder.
+#[derive(Debug, Clone)]
+pub struct FsStorage {
+    pub(crate) files: SeqFiles,
+    pub(crate) file_mode: Option<u32>,
+}
+
+impl FsStorage {
+    /// Keeps the sequence stored under `name` in `store_dir`, the unnamed one
+    /// if `name` is empty.
+    pub fn new<P: AsRef<Path>>(store_dir: P, name: &str) -> Self {
+        Self {
+            files: SeqFiles::new(store_dir, name),
+            file_mode: None,
+        }
+    }
+
+    fn slot_path(&self, slot: Slot) -> PathBuf {
+        match slot {
+            Slot::Version(n) => self.files.version_path(n),
+            Slot::Pending => self.files.tmp_path.clone(),
+            Slot::Metadata => self.files.meta_path.clone(),
+            Slot::PendingMetadata => self.files.meta_tmp_path.clone(),
+        }
+    }
+}
+
+impl SeqStorage for FsStorage {
+    fn read(&self, slot: Slot) -> io::Result<Vec<u8>> {
+        fs::read(self.slot_path(slot))
+    }
+
+    fn write(&self, slot: Slot, contents: &[u8], durability: Durability) -> io::Result<()> {
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        files::set_file_mode(&mut options, self.file_mode);
+        let mut f = options.open(self.slot_path(slot))?;
+        f.write_all(contents)?;
+        durability.sync_file(&f)
+    }
+
+    fn rename(&self, from: Slot, to: Slot) -> io::Result<()> {
+        fs::rename(self.slot_path(from), self.slot_path(to))
+    }
+
+    fn remove(&self, slot: Slot) -> io::Result<()> {
+        fs::remove_file(self.slot_path(slot))
+    }
+
+    fn exists(&self, slot: Slot) -> bool {
+        fs::metadata(self.slot_path(slot)).is_ok()
+    }
+
+    fn sync(&self, durability: Durability) -> io::Result<()> {
+        durability.sync_dir(&self.files.store_dir)
+    }
+
+    fn lock_path(&self) -> Option<&Path> {
+        Some(&self.files.lock_path)
+    }
+
+    fn path(&self, slot: Slot) -> PathBuf {
+        self.slot_path(slot)
+    }
+}
+
+/// Keeps a sequence in memory, e.g. to test code using it without touching
+/// the disk.
+///
+/// Clones share the slots and a mutex that takes the place of the lock file,
+/// like two handles on the same directory. Nothing survives the process.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStorage {
+    slots: Arc<Mutex<HashMap<Slot, Vec<u8>>>>,
+    mutex: Arc<Mutex<()>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn slots(&self) -> std::sync::MutexGuard<'_, HashMap<Slot, Vec<u8>>> {
+        // Every operation leaves the map consistent, even if it panicked
+        self.slots.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn not_found(slot: Slot) -> io::Error {
+    io::Error::new(ErrorKind::NotFound, format!("no {} slot", slot))
+}
+
+impl SeqStorage for MemoryStorage {
+    fn read(&self, slot: Slot) -> io::Result<Vec<u8>> {
+        self.slots()
+            .get(&slot)
+            .cloned()
+            .ok_or_else(|| not_found(slot))
+    }
+
+    fn write(&self, slot: Slot, contents: &[u8], _durability: Durability) -> io::Result<()> {
+        self.slots().insert(slot, contents.to_vec());
+        Ok(())
+    }
+
+    fn rename(&self, from: Slot, to: Slot) -> io::Result<()> {
+        let mut slots = self.slots();
+        let contents = slots.remove(&from).ok_or_else(|| not_found(from))?;
+        slots.insert(to, contents);
+        Ok(())
+    }
+
+    fn remove(&self, slot: Slot) -> io::Result<()> {
+        self.slots()
+            .remove(&slot)
+            .map(drop)
+            .ok_or_else(|| not_found(slot))
+    }
+
+    fn exists(&self, slot: Slot) -> bool {
+        self.slots().contains_key(&slot)
+    }
+
+    fn shared_mutex(&self) -> Option<&Mutex<()>> {
+        Some(&self.mutex)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::io::ErrorKind;
+    use std::thread;
+
+    use crate::storage::{FsStorage, MemoryStorage, SeqStorage, Slot};
+    use crate::tests::tmpdir;
+    use crate::{Durability, FileSeqBuilder, FileSeqError};
+
+    fn check_storage<S: SeqStorage>(storage: S) {
+        let durability = Durability::default();
+        assert!(!storage.exists(Slot::Version(2)));
+        let e = storage.read(Slot::Version(2)).unwrap_err();
+        assert_eq!(ErrorKind::NotFound, e.kind());
+
+        storage.write(Slot::Pending, b"one", durability).unwrap();
+        storage.write(Slot::Pending, b"two", durability).unwrap();
+        assert_eq!(b"two".to_vec(), storage.read(Slot::Pending).unwrap());
+
+        storage.write(Slot::Version(2), b"old", durability).unwrap();
+        storage.rename(Slot::Pending, Slot::Version(2)).unwrap();
+        storage.sync(durability).unwrap();
+        assert!(!storage.exists(Slot::Pending));
+        assert_eq!(b"two".to_vec(), storage.read(Slot::Version(2)).unwrap());
+        let e = storage.rename(Slot::Pending, Slot::Version(2)).unwrap_err();
+        assert_eq!(ErrorKind::NotFound, e.kind());
+
+        storage.remove(Slot::Version(2)).unwrap();
+        assert!(!storage.exists(Slot::Version(2)));
+        let e = storage.remove(Slot::Version(2)).unwrap_err();
+        assert_eq!(ErrorKind::NotFound, e.kind());
+    }
+
+    #[test]
+    fn should_keep_slots_in_files() {
+        let dir = tmpdir();
+        check_storage(FsStorage::new(&dir, "orders"));
+
+        let storage = FsStorage::new(&dir, "orders");
+        storage
+            .write(Slot::Version(1), b"1", Durability::default())
+            .unwrap();
+        assert_eq!(
+            b"1".to_vec(),
+            std::fs::read(dir.join("orders_1.seq")).unwrap()
+        );
+        assert_eq!(dir.join("orders_1.seq"), storage.path(Slot::Version(1)));
+        assert_eq!(Some(dir.join("orders.lock").as_path()), storage.lock_path());
+    }
+
+    #[test]
+    fn should_keep_slots_in_memory() {
+        check_storage(MemoryStorage::new());
+
+        let storage = MemoryStorage::new();
+        let clone = storage.clone();
+        storage
+            .write(Slot::Version(1), b"1", Durability::default())
+            .unwrap();
+        assert_eq!(b"1".to_vec(), clone.read(Slot::Version(1)).unwrap());
+        assert_eq!(None, storage.lock_path());
+    }
+
+    #[test]
+    fn should_use_sequence_in_memory() {
+        let dir = tmpdir().join("unused");
+        let storage = MemoryStorage::new();
+        let seq = FileSeqBuilder::new(&dir)
+            .initial_value(1)
+            .bounds(1, 10)
+            .build_with_storage(storage.clone())
+            .unwrap();
+        assert_eq!(2, seq.increment_and_get(1).unwrap());
+        assert_eq!(3, seq.increment_and_get(1).unwrap());
+        assert!(std::fs::metadata(&dir).is_err());
+
+        // Another handle adopts the stored bounds
+        let other = FileSeqBuilder::new(&dir)
+            .build_with_storage(storage.clone())
+            .unwrap();
+        assert!(matches!(other.set(11), Err(FileSeqError::OutOfBounds)));
+
+        storage
+            .write(Slot::Version(2), b"garbage", Durability::default())
+            .unwrap();
+        assert_eq!(2, other.value().unwrap());
+        assert!(!storage.exists(Slot::Version(2)));
+        assert!(!other.verify().unwrap().needs_repair());
+
+        seq.delete().unwrap();
+        assert!(!storage.exists(Slot::Version(1)));
+        assert!(!storage.exists(Slot::Metadata));
+    }
+
+    #[test]
+    fn should_serialize_handles_on_cloned_storage() {
+        let storage = MemoryStorage::new();
+        let builder = FileSeqBuilder::new("").initial_value(1);
+        let seqs = [
+            builder.clone().build_with_storage(storage.clone()).unwrap(),
+            builder.build_with_storage(storage.clone()).unwrap(),
+        ];
+        let values: Vec<u64> = thread::scope(|s| {
+            let handles: Vec<_> = seqs
+                .iter()
+                .map(|seq| {
+                    s.spawn(move || {
+                        (0..2000)
+                            .map(|_| seq.get_and_increment(1).unwrap())
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().unwrap())
+                .collect()
+        });
+        assert_eq!(4000, values.iter().collect::<HashSet<_>>().len());
+    }
+}
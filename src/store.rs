@@ -0,0 +1,186 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::{FileSeqError, Result};
+use crate::value::SeqValue;
+use crate::FileSeq;
+
+/// A directory holding any number of named sequences.
+///
+/// The files of a sequence are prefixed with its name, e.g. `orders_1.seq`,
+/// `orders_2.seq` and `orders.lock`. Names may only contain ASCII letters,
+/// digits, `-`, `_` and `.`, and must not start with `.`.
+///
+/// # Example
+///
+/// ```
+/// use file_seq::SeqStore;
+/// use std::path::Path;
+///
+/// let dir = Path::new("/tmp/example_store");
+/// # let _ = std::fs::remove_dir_all(&dir);
+///
+/// let store = SeqStore::open(&dir).unwrap();
+/// let orders = store.sequence("orders", 1).unwrap();
+/// let invoices = store.sequence("invoices", 100).unwrap();
+///
+/// assert_eq!(1, orders.get_and_increment(1).unwrap());
+/// assert_eq!(100, invoices.get_and_increment(1).unwrap());
+/// assert_eq!(vec!["invoices", "orders"], store.list().unwrap());
+/// ```
+#[derive(Debug, Clone)]
+pub struct SeqStore {
+    dir: PathBuf,
+}
+
+impl SeqStore {
+    /// Opens the store at `dir`, creating the directory if necessary.
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        fs::create_dir_all(dir.as_ref())?;
+        Ok(Self {
+            dir: dir.as_ref().to_path_buf(),
+        })
+    }
+
+    /// Opens the sequence `name`, creating it with `initial_value` if it
+    /// doesn't exist yet.
+    pub fn sequence(&self, name: &str, initial_value: u64) -> Result<FileSeq> {
+        self.sequence_typed(name, initial_value)
+    }
+
+    /// Same as [`SeqStore::sequence`], for value types other than `u64`.
+    pub fn sequence_typed<T: SeqValue>(&self, name: &str, initial_value: T) -> Result<FileSeq<T>> {
+        validate_name(name)?;
+        FileSeq::new_named(&self.dir, name, initial_value)
+    }
+
+    /// Returns whether the sequence `name` exists.
+    pub fn exists(&self, name: &str) -> Result<bool> {
+        validate_name(name)?;
+        Ok(FileSeq::<u64>::unopened_in(&self.dir, name)?.exists())
+    }
+
+    /// Returns the names of all sequences in the store, sorted.
+    pub fn list(&self) -> Result<Vec<String>> {
+        let mut names = BTreeSet::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let file_name = entry?.file_name();
+            let file_name = match file_name.to_str() {
+                Some(file_name) => file_name,
+                None => continue,
+            };
+            let name = file_name
+                .strip_suffix(".seq")
+                .and_then(|stem| stem.rsplit_once('_'))
+                .filter(|(_, n)| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+                .map(|(name, _)| name);
+            if let Some(name) = name {
+                if validate_name(name).is_ok() {
+                    names.insert(name.to_string());
+                }
+            }
+        }
+        Ok(names.into_iter().collect())
+    }
+
+    /// Deletes the sequence `name`.
+    ///
+    /// Its lock file is kept, as other processes might still be using it.
+    pub fn remove(&self, name: &str) -> Result<()> {
+        validate_name(name)?;
+        FileSeq::<u64>::unopened_in(&self.dir, name)?.delete()
+    }
+}
+
+pub(crate) fn validate_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.');
+    if valid {
+        Ok(())
+    } else {
+        Err(FileSeqError::InvalidName(name.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::tests::tmpdir;
+    use crate::{FileSeqError, SeqStore};
+
+    #[test]
+    fn should_keep_sequences_apart() {
+        let store = SeqStore::open(tmpdir()).unwrap();
+        let a = store.sequence("a", 1).unwrap();
+        let b = store.sequence("b", 10).unwrap();
+        assert_eq!(2, a.increment_and_get(1).unwrap());
+        assert_eq!(11, b.increment_and_get(1).unwrap());
+        assert_eq!(2, store.sequence("a", 1).unwrap().value().unwrap());
+    }
+
+    #[test]
+    fn should_list_sequences() {
+        let dir = tmpdir();
+        let store = SeqStore::open(&dir).unwrap();
+        assert!(store.list().unwrap().is_empty());
+        store
+            .sequence("orders", 1)
+            .unwrap()
+            .increment_and_get(1)
+            .unwrap();
+        store.sequence("tenant-1.orders", 1).unwrap();
+        store.sequence("a_1", 1).unwrap();
+        fs::write(dir.join("unrelated.txt"), "").unwrap();
+        assert_eq!(
+            vec!["a_1", "orders", "tenant-1.orders"],
+            store.list().unwrap()
+        );
+    }
+
+    #[test]
+    fn should_remove_sequence() {
+        let store = SeqStore::open(tmpdir()).unwrap();
+        store.sequence("orders", 1).unwrap();
+        assert!(store.exists("orders").unwrap());
+        store.remove("orders").unwrap();
+        assert!(!store.exists("orders").unwrap());
+        assert!(store.list().unwrap().is_empty());
+    }
+
+    #[test]
+    fn should_reject_invalid_names() {
+        let store = SeqStore::open(tmpdir()).unwrap();
+        for name in [
+            "",
+            ".",
+            "..",
+            "../escape",
+            "a/b",
+            "a\\b",
+            ".hidden",
+            "a b",
+            "ü",
+        ] {
+            let result = store.sequence(name, 1);
+            assert!(
+                matches!(result, Err(FileSeqError::InvalidName(_))),
+                "{:?}",
+                name
+            );
+            assert!(store.exists(name).is_err());
+            assert!(store.remove(name).is_err());
+        }
+    }
+
+    #[test]
+    fn should_open_typed_sequence() {
+        let store = SeqStore::open(tmpdir()).unwrap();
+        let seq = store.sequence_typed("signed", -1i64).unwrap();
+        assert_eq!(0, seq.increment_and_get(1).unwrap());
+    }
+}
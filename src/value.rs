@@ -0,0 +1,98 @@
+use std::convert::TryInto;
+use std::fmt::{Debug, Display};
+use std::str::FromStr;
+
+/// A type that can be stored in a sequence.
+///
+/// Implemented for `u32`, `u64`, `u128` and `i64`. Other types, e.g. newtypes
+/// around integers, can implement it too. Encoded values must be at most 255
+/// bytes long. The text [`Codec`](crate::Codec) stores values with `Display`
+/// and reads them back with `FromStr`.
+///
+/// Increments and decrements are expected to be non-negative. Moving past
+/// [`SeqValue::MIN`] or [`SeqValue::MAX`] is handled by the sequence's
+/// [`OverflowPolicy`](crate::OverflowPolicy).
+pub trait SeqValue: Copy + Ord + Debug + Display + FromStr + Send + Sync + 'static {
+    /// The smallest value of the type.
+    const MIN: Self;
+    /// The largest value of the type.
+    const MAX: Self;
+    /// The additive identity.
+    const ZERO: Self;
+    /// The value one step after zero.
+    const ONE: Self;
+
+    /// Appends the encoded value to `buf`.
+    fn encode(self, buf: &mut Vec<u8>);
+    /// Decodes a value written by [`SeqValue::encode`].
+    ///
+    /// Returns `None` if the bytes aren't a valid value of the type.
+    fn decode(bytes: &[u8]) -> Option<Self>;
+
+    /// Adds `rhs`, returning `None` on overflow.
+    fn checked_add(self, rhs: Self) -> Option<Self>;
+    /// Subtracts `rhs`, returning `None` on overflow.
+    fn checked_sub(self, rhs: Self) -> Option<Self>;
+    /// Returns the remainder of dividing by `rhs`, `None` if `rhs` is zero.
+    fn checked_rem(self, rhs: Self) -> Option<Self>;
+}
+
+macro_rules! impl_seq_value {
+    ($($t:ty),*) => {
+        $(
+            impl SeqValue for $t {
+                const MIN: Self = <$t>::MIN;
+                const MAX: Self = <$t>::MAX;
+                const ZERO: Self = 0;
+                const ONE: Self = 1;
+
+                fn encode(self, buf: &mut Vec<u8>) {
+                    buf.extend_from_slice(&self.to_be_bytes());
+                }
+
+                fn decode(bytes: &[u8]) -> Option<Self> {
+                    Some(<$t>::from_be_bytes(bytes.try_into().ok()?))
+                }
+
+                fn checked_add(self, rhs: Self) -> Option<Self> {
+                    <$t>::checked_add(self, rhs)
+                }
+
+                fn checked_sub(self, rhs: Self) -> Option<Self> {
+                    <$t>::checked_sub(self, rhs)
+                }
+
+                fn checked_rem(self, rhs: Self) -> Option<Self> {
+                    <$t>::checked_rem(self, rhs)
+                }
+            }
+        )*
+    };
+}
+
+impl_seq_value!(u32, u64, u128, i64);
+
+#[cfg(test)]
+mod tests {
+    use crate::SeqValue;
+
+    fn round_trip<T: SeqValue>(value: T) -> Option<T> {
+        let mut buf = Vec::new();
+        value.encode(&mut buf);
+        T::decode(&buf)
+    }
+
+    #[test]
+    fn should_round_trip() {
+        assert_eq!(Some(u32::MAX), round_trip(u32::MAX));
+        assert_eq!(Some(42u64), round_trip(42u64));
+        assert_eq!(Some(u128::MAX - 1), round_trip(u128::MAX - 1));
+        assert_eq!(Some(-42i64), round_trip(-42i64));
+    }
+
+    #[test]
+    fn should_reject_wrong_length() {
+        assert_eq!(None, u32::decode(&[0; 8]));
+        assert_eq!(None, u64::decode(&[0; 4]));
+    }
+}
@@ -0,0 +1,294 @@
+//! Reports on the state of the sequence files, for tooling and audits.
+
+use std::fmt;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use crate::error::FileSeqError;
+use crate::files::{self, FileState};
+use crate::record;
+use crate::storage::{SeqStorage, Slot};
+use crate::value::SeqValue;
+
+/// What a sequence file holds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FileStatus<T> {
+    /// The file doesn't exist.
+    Missing,
+    /// The file holds the beginning of a value, e.g. because the disk filled
+    /// up while writing it.
+    Truncated,
+    /// The file can't be read or doesn't hold a valid value.
+    Corrupted {
+        /// Why the file was rejected.
+        reason: String,
+    },
+    /// The file holds a valid value.
+    Valid(T),
+}
+
+impl<T: SeqValue> FileStatus<T> {
+    fn read<S: SeqStorage>(storage: &S, slot: Slot) -> Self {
+        let reason = match storage.read(slot) {
+            Ok(bytes) => match record::decode(&bytes) {
+                Ok(value) => return FileStatus::Valid(value),
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => return FileStatus::Truncated,
+                Err(e) => e.to_string(),
+            },
+            Err(e) if e.kind() == ErrorKind::NotFound => return FileStatus::Missing,
+            Err(e) => e.to_string(),
+        };
+        FileStatus::Corrupted { reason }
+    }
+
+    fn state(&self) -> FileState<T> {
+        match self {
+            FileStatus::Missing => FileState::Missing,
+            FileStatus::Truncated | FileStatus::Corrupted { .. } => FileState::Corrupted,
+            FileStatus::Valid(value) => FileState::Valid(*value),
+        }
+    }
+}
+
+/// One of the sequence files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct FileReport<T> {
+    /// Where the file is kept, see [`SeqStorage::path`].
+    pub path: PathBuf,
+    pub status: FileStatus<T>,
+}
+
+/// Which file the value of a sequence is read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Version {
+    /// The file holding the value before the latest one, `_1.seq` with the
+    /// default two versions.
+    Backup,
+    /// The file holding the latest value, `_2.seq` with the default two
+    /// versions.
+    Latest,
+    /// One of the files older than the backup, `_n.seq`, see
+    /// [`FileSeqBuilder::versions`](crate::FileSeqBuilder::versions).
+    Older(u32),
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Version::Backup => f.write_str("backup"),
+            Version::Latest => f.write_str("latest"),
+            Version::Older(n) => write!(f, "version {}", n),
+        }
+    }
+}
+
+/// A skip ahead made after the value was recovered from the backup, see
+/// [`FileSeqBuilder::recovery_gap`](crate::FileSeqBuilder::recovery_gap).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Skip<T> {
+    /// The value read from the backup.
+    pub from: T,
+    /// The value the sequence continued with.
+    pub to: T,
+}
+
+/// The state of the sequence files and the decision reading the sequence
+/// makes based on it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct VerifyReport<T> {
+    /// The files older than the backup, oldest first, empty with the
+    /// default two versions.
+    pub older: Vec<FileReport<T>>,
+    pub backup: FileReport<T>,
+    pub latest: FileReport<T>,
+    /// The file the value is read from, `None` if the sequence can't be read.
+    pub selected: Option<Version>,
+    /// Why `selected` was chosen.
+    pub reason: String,
+}
+
+impl<T: SeqValue> VerifyReport<T> {
+    /// Reads the `versions` files of the sequence in `storage`.
+    pub(crate) fn read<S: SeqStorage>(storage: &S, versions: u32) -> Self {
+        let mut files: Vec<_> = (1..=versions)
+            .map(|n| FileReport {
+                path: storage.path(Slot::Version(n)),
+                status: FileStatus::read(storage, Slot::Version(n)),
+            })
+            .collect();
+        let states: Vec<_> = files.iter().map(|file| file.status.state()).collect();
+
+        let (selected, reason) = match files::recover(&states) {
+            Ok(recovered) => {
+                let selected = match versions - recovered.version as u32 {
+                    1 => Version::Latest,
+                    2 => Version::Backup,
+                    _ => Version::Older(recovered.version as u32 + 1),
+                };
+                let using = match selected {
+                    Version::Older(_) => "an older version",
+                    _ => "the backup",
+                };
+                let reason = match states.last() {
+                    Some(FileState::Valid(_)) => "the latest file is valid".to_string(),
+                    Some(FileState::Missing) => {
+                        format!("the latest file is missing, using {}", using)
+                    }
+                    _ => format!("the latest file is truncated or corrupted, using {}", using),
+                };
+                (Some(selected), reason)
+            }
+            Err(FileSeqError::NotFound) => (None, "the sequence does not exist".to_string()),
+            Err(_) => (None, "no file holds a valid value".to_string()),
+        };
+
+        let latest = files.pop().expect("at least two versions");
+        let backup = files.pop().expect("at least two versions");
+        Self {
+            older: files,
+            backup,
+            latest,
+            selected,
+            reason,
+        }
+    }
+
+    /// Returns all files, oldest first.
+    fn files(&self) -> Vec<&FileReport<T>> {
+        let mut files: Vec<_> = self.older.iter().collect();
+        files.push(&self.backup);
+        files.push(&self.latest);
+        files
+    }
+
+    /// Returns the index of the selected file in [`files`](Self::files).
+    fn selected_index(&self) -> Option<usize> {
+        Some(match self.selected? {
+            Version::Older(n) => n as usize - 1,
+            Version::Backup => self.older.len(),
+            Version::Latest => self.older.len() + 1,
+        })
+    }
+
+    /// Returns whether [`FileSeq::repair`](crate::FileSeq::repair) would
+    /// change the files.
+    pub fn needs_repair(&self) -> bool {
+        match self.selected_index() {
+            Some(selected) => self.files()[selected + 1..]
+                .iter()
+                .any(|file| file.status != FileStatus::Missing),
+            None => false,
+        }
+    }
+
+    /// Returns the value of the sequence, `None` if it can't be read.
+    pub fn value(&self) -> Option<T> {
+        match self.files()[self.selected_index()?].status {
+            FileStatus::Valid(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::tests::tmpdir;
+    use crate::{FileSeq, FileSeqError, FileStatus, Version};
+
+    #[test]
+    fn should_report_valid_files() {
+        let dir = tmpdir();
+        let seq = FileSeq::new(&dir, 1).unwrap();
+        seq.increment_and_get(1).unwrap();
+        let report = seq.verify().unwrap();
+        assert_eq!(FileStatus::Valid(1), report.backup.status);
+        assert_eq!(FileStatus::Valid(2), report.latest.status);
+        assert_eq!(dir.join("_2.seq"), report.latest.path);
+        assert_eq!(Some(Version::Latest), report.selected);
+        assert_eq!(Some(2), report.value());
+    }
+
+    #[test]
+    fn should_report_corrupted_latest_without_removing_it() {
+        let dir = tmpdir();
+        let seq = FileSeq::new(&dir, 1).unwrap();
+        seq.increment_and_get(1).unwrap();
+        fs::write(dir.join("_2.seq"), [0xff; 12]).unwrap();
+        let report = seq.verify().unwrap();
+        assert!(matches!(report.latest.status, FileStatus::Corrupted { .. }));
+        assert_eq!(Some(Version::Backup), report.selected);
+        assert_eq!(Some(1), report.value());
+        assert!(fs::metadata(dir.join("_2.seq")).is_ok());
+    }
+
+    #[test]
+    fn should_report_truncated_latest() {
+        let dir = tmpdir();
+        let seq = FileSeq::new(&dir, 1).unwrap();
+        seq.increment_and_get(1).unwrap();
+        let latest = fs::read(dir.join("_2.seq")).unwrap();
+        fs::write(dir.join("_2.seq"), &latest[..latest.len() - 1]).unwrap();
+        let report = seq.verify().unwrap();
+        assert_eq!(FileStatus::Truncated, report.latest.status);
+        assert_eq!(Some(Version::Backup), report.selected);
+        assert!(report.needs_repair());
+    }
+
+    #[test]
+    fn should_repair_corrupted_latest() {
+        let dir = tmpdir();
+        let seq = FileSeq::new(&dir, 1).unwrap();
+        seq.increment_and_get(1).unwrap();
+        fs::write(dir.join("_2.seq"), [0xff; 12]).unwrap();
+        fs::write(dir.join(".seq.tmp"), [0xff; 3]).unwrap();
+
+        let report = seq.repair().unwrap();
+        assert!(report.needs_repair());
+        assert!(fs::metadata(dir.join("_2.seq")).is_err());
+        assert!(fs::metadata(dir.join(".seq.tmp")).is_err());
+        assert!(!seq.verify().unwrap().needs_repair());
+        assert!(!seq.repair().unwrap().needs_repair());
+        assert_eq!(2, seq.increment_and_get(1).unwrap());
+    }
+
+    #[test]
+    fn should_not_repair_unreadable_sequence() {
+        let dir = tmpdir();
+        let seq = FileSeq::new(&dir, 1).unwrap();
+        fs::write(dir.join("_2.seq"), [0xff; 12]).unwrap();
+        assert!(matches!(seq.repair(), Err(FileSeqError::Corrupted)));
+        assert!(fs::metadata(dir.join("_2.seq")).is_ok());
+    }
+
+    #[test]
+    fn should_report_missing_latest() {
+        let dir = tmpdir();
+        let seq = FileSeq::new(&dir, 1).unwrap();
+        seq.increment_and_get(1).unwrap();
+        fs::remove_file(dir.join("_2.seq")).unwrap();
+        let report = seq.verify().unwrap();
+        assert_eq!(FileStatus::Missing, report.latest.status);
+        assert_eq!(Some(Version::Backup), report.selected);
+    }
+
+    #[test]
+    fn should_report_unreadable_sequence() {
+        let dir = tmpdir();
+        let seq = FileSeq::new(&dir, 1).unwrap();
+        seq.delete().unwrap();
+        let report = seq.verify().unwrap();
+        assert_eq!(FileStatus::Missing, report.backup.status);
+        assert_eq!(None, report.selected);
+        assert_eq!(None, report.value());
+
+        fs::write(dir.join("_2.seq"), [0xff; 12]).unwrap();
+        let report = seq.verify().unwrap();
+        assert_eq!(None, report.selected);
+    }
+}
@@ -0,0 +1,126 @@
+//! Runs the `file-seq` binary against temporary stores.
+
+#![cfg(feature = "cli")]
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+use rand::RngCore;
+
+fn tmpdir() -> PathBuf {
+    let p = env::temp_dir();
+    let mut r = rand::thread_rng();
+    let ret = p.join(format!("file-seq-{}", r.next_u32()));
+    fs::create_dir(&ret).unwrap();
+    ret
+}
+
+fn file_seq(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_file-seq"))
+        .arg(dir)
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    assert!(output.status.success(), "{:?}", output);
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+#[test]
+fn should_manipulate_sequence() {
+    let dir = tmpdir();
+    assert_eq!("5\n", stdout(&file_seq(&dir, &["init", "5"])));
+    assert_eq!("6\n", stdout(&file_seq(&dir, &["incr"])));
+    assert_eq!("16\n", stdout(&file_seq(&dir, &["incr", "10"])));
+    assert_eq!("16\n", stdout(&file_seq(&dir, &["get"])));
+    assert_eq!("3\n", stdout(&file_seq(&dir, &["set", "3"])));
+    assert_eq!("deleted\n", stdout(&file_seq(&dir, &["delete"])));
+    assert!(!file_seq(&dir, &["get"]).status.success());
+}
+
+#[test]
+fn should_not_create_sequence_implicitly() {
+    let dir = tmpdir();
+    for args in [&["get"][..], &["incr"], &["set", "1"], &["inspect"]] {
+        assert!(!file_seq(&dir, args).status.success());
+    }
+    assert_eq!(
+        0,
+        fs::read_dir(&dir)
+            .unwrap()
+            .filter(|e| {
+                !e.as_ref()
+                    .unwrap()
+                    .file_name()
+                    .to_string_lossy()
+                    .ends_with(".lock")
+            })
+            .count()
+    );
+    stdout(&file_seq(&dir, &["init", "1"]));
+    assert!(!file_seq(&dir, &["init", "1"]).status.success());
+}
+
+#[test]
+fn should_use_named_typed_sequence() {
+    let dir = tmpdir();
+    let args = ["--name", "orders", "--type", "i64"];
+    assert_eq!(
+        "-3\n",
+        stdout(&file_seq(&dir, &[&args[..], &["init", "-3"]].concat()))
+    );
+    assert_eq!(
+        "-2\n",
+        stdout(&file_seq(&dir, &[&args[..], &["incr"]].concat()))
+    );
+    assert!(fs::metadata(dir.join("orders_2.seq")).is_ok());
+}
+
+#[test]
+fn should_print_json() {
+    let dir = tmpdir();
+    stdout(&file_seq(&dir, &["init", "1"]));
+    assert_eq!(
+        "{\"value\":2}\n",
+        stdout(&file_seq(&dir, &["--json", "incr"]))
+    );
+
+    let output = file_seq(&dir, &["--json", "--name", "missing", "get"]);
+    assert!(!output.status.success());
+    assert_eq!(
+        "{\"error\":\"sequence does not exist\"}\n",
+        String::from_utf8(output.stdout).unwrap()
+    );
+}
+
+#[test]
+fn should_inspect_and_repair() {
+    let dir = tmpdir();
+    stdout(&file_seq(&dir, &["init", "1"]));
+    stdout(&file_seq(&dir, &["incr"]));
+    fs::write(dir.join("_2.seq"), "xx").unwrap();
+
+    let report = stdout(&file_seq(&dir, &["--json", "inspect"]));
+    assert!(report.contains("\"latest\":{"), "{}", report);
+    assert!(report.contains("\"status\":\"truncated\""), "{}", report);
+    assert!(report.contains("\"selected\":\"backup\""), "{}", report);
+    assert!(report.contains("\"value\":1}"), "{}", report);
+    // Only repair touches the files
+    assert_eq!("1\n", stdout(&file_seq(&dir, &["get"])));
+    assert!(fs::metadata(dir.join("_2.seq")).is_ok());
+
+    let repaired = stdout(&file_seq(&dir, &["--json", "repair"]));
+    assert!(repaired.starts_with("{\"repaired\":true,"), "{}", repaired);
+    assert!(fs::metadata(dir.join("_2.seq")).is_err());
+
+    fs::write(dir.join("_2.seq"), "xx").unwrap();
+    let repaired = stdout(&file_seq(&dir, &["repair"]));
+    let removed = format!("removed {}\n", dir.join("_2.seq").display());
+    assert!(repaired.starts_with(&removed), "{}", repaired);
+    let repaired = stdout(&file_seq(&dir, &["repair"]));
+    assert!(repaired.starts_with("nothing to repair"), "{}", repaired);
+}
@@ -0,0 +1,377 @@
+//! Crashes the process and fails storage operations at every point of a
+//! workload, and checks the sequence never goes back to a value it already
+//! handed out.
+//!
+//! A crash is simulated by failing every storage operation from that point
+//! on and reopening the sequence on what the storage kept. Operations that
+//! completed before the crash are assumed to be durable, the write in flight
+//! may be truncated or garbled.
+
+use std::cell::Cell;
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use file_seq::{Codec, Durability, FileSeqBuilder, FsStorage, MemoryStorage, SeqStorage, Slot};
+use rand::RngCore;
+
+const ROUNDS: usize = 4;
+
+fn tmpdir() -> PathBuf {
+    let p = env::temp_dir();
+    let mut r = rand::thread_rng();
+    let ret = p.join(format!("file-seq-{}", r.next_u32()));
+    fs::create_dir(&ret).unwrap();
+    ret
+}
+
+/// What goes wrong at one storage operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Fault {
+    /// The process crashes before the operation.
+    Crash,
+    /// The process crashes while writing, after the first `n` bytes.
+    Torn(usize),
+    /// The process crashes while writing, after writing everything with bit
+    /// `n` flipped.
+    FlipBit(usize),
+    /// The operation fails without any effect, the process carries on.
+    Fail,
+}
+
+#[derive(Debug, Default)]
+struct Injector {
+    /// The fault and the index of the operation it hits.
+    fault: Option<(usize, Fault)>,
+    crashed: bool,
+    /// Every operation that changes the storage, with the length of the
+    /// contents for writes.
+    ops: Vec<Option<usize>>,
+}
+
+/// Wraps a storage and injects a fault into one of its operations.
+#[derive(Debug)]
+struct FaultyStorage<S> {
+    inner: S,
+    injector: Arc<Mutex<Injector>>,
+}
+
+impl<S> FaultyStorage<S> {
+    fn new(inner: S, fault: Option<(usize, Fault)>) -> Self {
+        Self {
+            inner,
+            injector: Arc::new(Mutex::new(Injector {
+                fault,
+                ..Injector::default()
+            })),
+        }
+    }
+
+    fn check_crashed(&self) -> io::Result<()> {
+        if self.injector.lock().unwrap().crashed {
+            Err(crashed())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Records an operation and returns the fault hitting it.
+    fn next_op(&self, len: Option<usize>) -> io::Result<Option<Fault>> {
+        let mut injector = self.injector.lock().unwrap();
+        if injector.crashed {
+            return Err(crashed());
+        }
+        let index = injector.ops.len();
+        injector.ops.push(len);
+        let fault = match injector.fault {
+            Some((at, fault)) if at == index => fault,
+            _ => return Ok(None),
+        };
+        injector.crashed = fault != Fault::Fail;
+        Ok(Some(fault))
+    }
+}
+
+fn crashed() -> io::Error {
+    io::Error::other("crashed")
+}
+
+fn failed() -> io::Error {
+    io::Error::other("injected failure")
+}
+
+impl<S: SeqStorage> SeqStorage for FaultyStorage<S> {
+    fn read(&self, slot: Slot) -> io::Result<Vec<u8>> {
+        self.check_crashed()?;
+        self.inner.read(slot)
+    }
+
+    fn write(&self, slot: Slot, contents: &[u8], durability: Durability) -> io::Result<()> {
+        match self.next_op(Some(contents.len()))? {
+            None => self.inner.write(slot, contents, durability),
+            Some(Fault::Crash) => Err(crashed()),
+            Some(Fault::Torn(n)) => {
+                let n = n.min(contents.len());
+                self.inner.write(slot, &contents[..n], durability)?;
+                Err(crashed())
+            }
+            Some(Fault::FlipBit(n)) => {
+                let mut garbled = contents.to_vec();
+                let n = n % (garbled.len() * 8);
+                garbled[n / 8] ^= 1 << (n % 8);
+                self.inner.write(slot, &garbled, durability)?;
+                Err(crashed())
+            }
+            Some(Fault::Fail) => Err(failed()),
+        }
+    }
+
+    fn rename(&self, from: Slot, to: Slot) -> io::Result<()> {
+        match self.next_op(None)? {
+            None => self.inner.rename(from, to),
+            Some(Fault::Fail) => Err(failed()),
+            Some(_) => Err(crashed()),
+        }
+    }
+
+    fn remove(&self, slot: Slot) -> io::Result<()> {
+        match self.next_op(None)? {
+            None => self.inner.remove(slot),
+            Some(Fault::Fail) => Err(failed()),
+            Some(_) => Err(crashed()),
+        }
+    }
+
+    fn exists(&self, slot: Slot) -> bool {
+        self.check_crashed().is_ok() && self.inner.exists(slot)
+    }
+
+    fn sync(&self, durability: Durability) -> io::Result<()> {
+        self.check_crashed()?;
+        self.inner.sync(durability)
+    }
+
+    fn lock_path(&self) -> Option<&Path> {
+        self.inner.lock_path()
+    }
+
+    fn shared_mutex(&self) -> Option<&Mutex<()>> {
+        self.inner.shared_mutex()
+    }
+
+    fn path(&self, slot: Slot) -> PathBuf {
+        self.inner.path(slot)
+    }
+}
+
+fn builder(codec: Codec) -> FileSeqBuilder {
+    FileSeqBuilder::new("unused")
+        .initial_value(1)
+        .codec(codec)
+        .durability(Durability::None)
+}
+
+/// Creates the sequence and hands out values with `next` and `reserve`,
+/// returning the operations the storage saw.
+fn workload<S: SeqStorage>(
+    storage: FaultyStorage<S>,
+    codec: Codec,
+    handed_out: &mut Vec<u64>,
+) -> Vec<Option<usize>> {
+    let injector = storage.injector.clone();
+    if let Ok(seq) = builder(codec).build_with_storage(storage) {
+        for round in 0..ROUNDS {
+            let result = if round % 2 == 0 {
+                seq.next()
+            } else {
+                seq.reserve(3).map(|block| block.end - 1)
+            };
+            // After a failure, the process carries on with the next round
+            if let Ok(value) = result {
+                handed_out.push(value);
+            }
+        }
+    }
+    let ops = injector.lock().unwrap().ops.clone();
+    ops
+}
+
+/// Reopens the sequence after a crash and hands out one more value.
+fn recovery<S: SeqStorage>(
+    storage: FaultyStorage<S>,
+    codec: Codec,
+    handed_out: &mut Vec<u64>,
+    scenario: &str,
+) -> Vec<Option<usize>> {
+    let injector = storage.injector.clone();
+    if let Ok(seq) = builder(codec).build_with_storage(storage) {
+        if let Ok(value) = seq.value() {
+            check_above(value, handed_out, scenario);
+        }
+        if let Ok(value) = seq.next() {
+            handed_out.push(value);
+        }
+    }
+    let ops = injector.lock().unwrap().ops.clone();
+    ops
+}
+
+fn check_above(value: u64, handed_out: &[u64], scenario: &str) {
+    if let Some(max) = handed_out.iter().max() {
+        assert!(
+            value > *max,
+            "{}: read {} after handing out {}",
+            scenario,
+            value,
+            max
+        );
+    }
+}
+
+/// Runs the workload with `first`, a recovery with `second` and a final
+/// recovery without faults, returning the operations of the first recovery.
+fn scenario<S: SeqStorage + Clone>(
+    storage: S,
+    codec: Codec,
+    first: Option<(usize, Fault)>,
+    second: Option<(usize, Fault)>,
+) -> Vec<Option<usize>> {
+    let name = format!("{:?}, {:?} then {:?}", codec, first, second);
+    let mut handed_out = Vec::new();
+    workload(
+        FaultyStorage::new(storage.clone(), first),
+        codec,
+        &mut handed_out,
+    );
+    let ops = recovery(
+        FaultyStorage::new(storage.clone(), second),
+        codec,
+        &mut handed_out,
+        &name,
+    );
+
+    let seq = builder(codec).build_with_storage(storage).unwrap();
+    let value = seq
+        .value()
+        .unwrap_or_else(|e| panic!("{}: failed to read: {}", name, e));
+    check_above(value, &handed_out, &name);
+    let unique: HashSet<_> = handed_out.iter().collect();
+    assert_eq!(unique.len(), handed_out.len(), "{}: {:?}", name, handed_out);
+    ops
+}
+
+/// Returns every fault for the given operations, bit flips and truncations
+/// only if `garble` is set.
+fn faults(ops: &[Option<usize>], garble: bool) -> Vec<(usize, Fault)> {
+    let mut faults = Vec::new();
+    for (at, len) in ops.iter().enumerate() {
+        faults.push((at, Fault::Crash));
+        faults.push((at, Fault::Fail));
+        match len {
+            Some(len) if garble => {
+                faults.extend((0..*len).map(|n| (at, Fault::Torn(n))));
+                faults.extend((0..len * 8).map(|n| (at, Fault::FlipBit(n))));
+            }
+            Some(_) => faults.push((at, Fault::Torn(0))),
+            None => {}
+        }
+    }
+    faults
+}
+
+/// Runs every fault during the workload, each followed by every crash and
+/// failure during the recovery.
+fn check_all_faults<S: SeqStorage + Clone, F: Fn() -> S>(storage: F, codec: Codec, garble: bool) {
+    let ops = workload(FaultyStorage::new(storage(), None), codec, &mut Vec::new());
+    assert_eq!(4 + ROUNDS * 3, ops.len(), "{:?}", ops);
+
+    for first in faults(&ops, garble) {
+        let recovery_ops = scenario(storage(), codec, Some(first), None);
+        for second in faults(&recovery_ops, false) {
+            scenario(storage(), codec, Some(first), Some(second));
+        }
+    }
+}
+
+#[test]
+fn should_survive_every_fault_in_memory() {
+    for codec in [Codec::Binary, Codec::Text] {
+        check_all_faults(MemoryStorage::new, codec, true);
+    }
+}
+
+#[test]
+fn should_survive_every_fault_on_disk() {
+    let base = tmpdir();
+    let runs = Cell::new(0);
+    let storage = || {
+        runs.set(runs.get() + 1);
+        let dir = base.join(runs.get().to_string());
+        fs::create_dir(&dir).unwrap();
+        FsStorage::new(dir, "")
+    };
+    for codec in [Codec::Binary, Codec::Text] {
+        check_all_faults(storage, codec, false);
+    }
+    fs::remove_dir_all(&base).unwrap();
+}
+
+#[test]
+fn should_recover_after_crash_between_renames() {
+    let storage = MemoryStorage::new();
+    let mut handed_out = Vec::new();
+    // Creating the sequence takes 4 operations and every round 3, so this
+    // crashes between the renames of the second round
+    workload(
+        FaultyStorage::new(storage.clone(), Some((4 + 3 + 2, Fault::Crash))),
+        Codec::Binary,
+        &mut handed_out,
+    );
+    assert_eq!(vec![1], handed_out);
+    assert!(storage.exists(Slot::Version(1)));
+    assert!(!storage.exists(Slot::Version(2)));
+
+    let seq = builder(Codec::Binary).build_with_storage(storage).unwrap();
+    assert_eq!(2, seq.value().unwrap());
+}
+
+#[test]
+fn should_not_repeat_values_after_latest_is_corrupted_with_recovery_gap() {
+    for codec in [Codec::Binary, Codec::Text] {
+        let latest_len = {
+            let storage = MemoryStorage::new();
+            workload(
+                FaultyStorage::new(storage.clone(), None),
+                codec,
+                &mut Vec::new(),
+            );
+            storage.read(Slot::Version(2)).unwrap().len()
+        };
+        for bit in 0..latest_len * 8 {
+            let storage = MemoryStorage::new();
+            let mut handed_out = Vec::new();
+            workload(
+                FaultyStorage::new(storage.clone(), None),
+                codec,
+                &mut handed_out,
+            );
+            // Corrupted after it was written, e.g. by the disk
+            let mut latest = storage.read(Slot::Version(2)).unwrap();
+            latest[bit / 8] ^= 1 << (bit % 8);
+            storage
+                .write(Slot::Version(2), &latest, Durability::None)
+                .unwrap();
+
+            // The largest increment of the workload is a block of 3
+            let seq = builder(codec)
+                .recovery_gap(3)
+                .build_with_storage(storage)
+                .unwrap();
+            let name = format!("{:?}, bit {} flipped", codec, bit);
+            check_above(seq.next().unwrap(), &handed_out, &name);
+        }
+    }
+}
@@ -0,0 +1,79 @@
+//! Runs several processes against one store directory.
+//!
+//! The test binary re-executes itself with `WORKER_DIR_VAR` set, in which
+//! case `multi_process_worker` acts as the worker instead of a test.
+
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use file_seq::FileSeq;
+use rand::RngCore;
+
+const WORKER_DIR_VAR: &str = "FILE_SEQ_WORKER_DIR";
+const WORKERS: usize = 6;
+const INCREMENTS_PER_WORKER: usize = 200;
+
+fn tmpdir() -> PathBuf {
+    let p = env::temp_dir();
+    let mut r = rand::thread_rng();
+    let ret = p.join(format!("file-seq-{}", r.next_u32()));
+    fs::create_dir(&ret).unwrap();
+    ret
+}
+
+#[test]
+fn multi_process_worker() {
+    let dir = match env::var(WORKER_DIR_VAR) {
+        Ok(dir) => dir,
+        Err(_) => return,
+    };
+    let seq = FileSeq::new(&dir, 1).unwrap();
+    for _ in 0..INCREMENTS_PER_WORKER {
+        println!("value={}", seq.get_and_increment(1).unwrap());
+    }
+}
+
+#[test]
+fn should_not_hand_out_duplicates_across_processes() {
+    let dir = tmpdir();
+    FileSeq::new(&dir, 1).unwrap();
+
+    let exe = env::current_exe().unwrap();
+    let children: Vec<_> = (0..WORKERS)
+        .map(|_| {
+            Command::new(&exe)
+                .args([
+                    "multi_process_worker",
+                    "--exact",
+                    "--nocapture",
+                    "--test-threads=1",
+                ])
+                .env(WORKER_DIR_VAR, &dir)
+                .stdout(Stdio::piped())
+                .spawn()
+                .unwrap()
+        })
+        .collect();
+
+    let mut values = HashSet::new();
+    for child in children {
+        let output = child.wait_with_output().unwrap();
+        assert!(output.status.success());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        // The harness prints its own status around the worker output
+        for token in stdout.split_whitespace() {
+            if let Some(value) = token.strip_prefix("value=") {
+                let value: u64 = value.parse().unwrap();
+                assert!(values.insert(value), "{} was handed out twice", value);
+            }
+        }
+    }
+
+    let total = (WORKERS * INCREMENTS_PER_WORKER) as u64;
+    assert_eq!(total as usize, values.len());
+    assert_eq!(values, (1..=total).collect());
+    assert_eq!(total + 1, FileSeq::new(&dir, 1).unwrap().value().unwrap());
+}